@@ -1,37 +1,43 @@
 use crate::{
-    lexer::Token,
+    diagnostic::Diagnostic,
+    lexer::{Token, Tokens},
     parser::{
-        AssignmentStmts, Ast, DeclarationStmts, Declarations, Expression, FnSignature, Functions,
-        ReturnStmts, Scope, Statement,
+        AssignmentKinds, AssignmentStmts, Ast, BreakStmts, Conditionals, ContinueStmts,
+        DeclarationStmts, Declarations, ElseBranch, Expression, FnSignature, Functions, LoopKinds,
+        Loops, Place, ReturnStmts, Scope, Statement,
     },
+    sema::unknown_type,
 };
-use std::fmt::Display;
-
-#[derive(Debug)]
-pub struct CodegenError;
-impl std::error::Error for CodegenError {}
-impl Display for CodegenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "codegen error")
-    }
-}
 
-pub fn c_codegen(ast: Ast) -> Result<String, CodegenError> {
+pub fn c_codegen(ast: Ast) -> Result<String, Diagnostic> {
+    // forward declare every function so calls can appear before definitions
+    let prototypes = ast
+        .functions
+        .iter()
+        .map(|func| Ok(codegen_function_signature(&func.fn_signature)? + ";\n"))
+        .collect::<Result<Vec<String>, Diagnostic>>()?
+        .concat();
+
     let codegen = ast
         .functions
         .into_iter()
-        .map(|func| codegen_function(func))
-        .fold(Ok(String::new()), |sum_code, fn_string| {
-            Ok(sum_code? + &fn_string?.to_string())
-        })?;
+        .map(codegen_function)
+        .collect::<Result<Vec<String>, Diagnostic>>()?
+        .concat();
 
-    let result = format!("#include <stdbool.h>\n{codegen}");
+    let result = format!(
+        "#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n{STR_TYPE}{prototypes}{codegen}"
+    );
 
     Ok(result)
 }
 
-fn codegen_function(func: Functions) -> Result<String, CodegenError> {
-    let fn_signature = codegen_function_signature(func.fn_signature)?;
+/// `str` is a pointer and a length, the contents are not nul terminated as
+/// far as the language is concerned.
+const STR_TYPE: &str = "typedef struct {\n\tconst char *ptr;\n\tsize_t len;\n} __str;\n";
+
+fn codegen_function(func: Functions) -> Result<String, Diagnostic> {
+    let fn_signature = codegen_function_signature(&func.fn_signature)?;
     let scope = codegen_scope(func.scope)?;
 
     let buf = format!("{fn_signature}{scope}");
@@ -39,96 +45,435 @@ fn codegen_function(func: Functions) -> Result<String, CodegenError> {
     Ok(buf)
 }
 
-fn codegen_function_signature(fn_signature: FnSignature) -> Result<String, CodegenError> {
-    let ret_type = to_native_type(&fn_signature.return_type).ok_or(CodegenError)?;
+fn codegen_function_signature(fn_signature: &FnSignature) -> Result<String, Diagnostic> {
+    let ret_type = to_native_type(&fn_signature.return_type)
+        .ok_or_else(|| unknown_type(&fn_signature.return_type, fn_signature.return_type_span))?;
+
+    let params = if fn_signature.params.is_empty() {
+        "void".to_string()
+    } else {
+        fn_signature
+            .params
+            .iter()
+            .map(codegen_declaration)
+            .collect::<Result<Vec<String>, Diagnostic>>()?
+            .join(", ")
+    };
 
-    Ok(format!("{} {}()", ret_type, fn_signature.name))
+    Ok(format!("{} {}({})", ret_type, fn_signature.name, params))
 }
 
-fn codegen_scope(func: Scope) -> Result<String, CodegenError> {
-    let start = format!("{{\n");
-    let end = format!("}}\n");
-    let statements = func
-        .stmts
-        .into_iter()
-        .map(|stmt| codegen_statement(stmt))
-        .fold(Ok(String::new()), |sum_code, fn_string| {
-            Ok(sum_code? + &fn_string?.to_string())
-        })?;
+fn codegen_scope(func: Scope) -> Result<String, Diagnostic> {
+    let start = "{\n".to_string();
+    let end = "}\n".to_string();
+    let statements = codegen_statements(func.stmts)?;
+
+    // a function body evaluates to what the function returns
+    let return_statement = match (func.ret, func.value) {
+        (Some(return_stmt), _) => codegen_return_statement(return_stmt)?,
+        (None, Some(value)) => match *value {
+            // a loop that is never left has nothing to return
+            Expression::Loop(loops) if loops.value_type.is_none() => {
+                format!("\t{}\n", codegen_loop(*loops)?)
+            }
+            value => format!("\treturn {};\n", codegen_expression(value)?),
+        },
+        (None, None) => "".to_string(),
+    };
 
-    let return_statement = func.ret.map_or(Ok("".to_string()), |return_stmt| {
+    Ok(format!("{start}{statements}{return_statement}{end}"))
+}
+
+/// Statements of a nested block, without the braces. The value of the block
+/// is only evaluated for its side effects.
+fn codegen_block(scope: Scope) -> Result<String, Diagnostic> {
+    let statements = codegen_statements(scope.stmts)?;
+
+    let value = match scope.value.map(|value| *value) {
+        Some(Expression::Loop(loops)) => format!("\t{}\n", codegen_loop(*loops)?),
+        Some(value) => format!("\t{};\n", codegen_expression(value)?),
+        None => "".to_string(),
+    };
+
+    let return_statement = scope.ret.map_or(Ok("".to_string()), |return_stmt| {
         codegen_return_statement(return_stmt)
     })?;
 
-    Ok(format!("{start}{statements}{return_statement}{end}"))
+    Ok(format!("{statements}{value}{return_statement}"))
+}
+
+fn codegen_statements(stmts: Vec<Statement>) -> Result<String, Diagnostic> {
+    Ok(stmts
+        .into_iter()
+        .map(codegen_statement)
+        .collect::<Result<Vec<String>, Diagnostic>>()?
+        .concat())
+}
+
+/// Adds one level of indentation to every line of `code`.
+fn indent(code: String) -> String {
+    code.lines().map(|line| format!("\t{line}\n")).collect()
 }
 
-fn codegen_statement(statement: Statement) -> Result<String, CodegenError> {
+fn codegen_statement(statement: Statement) -> Result<String, Diagnostic> {
     match statement {
         Statement::Return(return_stmt) => codegen_return_statement(return_stmt),
         Statement::Assignment(assignment_stmt) => codegen_assignment(assignment_stmt),
         Statement::MutDeclaration(mut_decl_stmt) => codegen_mut_declaration(mut_decl_stmt),
         Statement::ConstDeclaration(const_decl_stmt) => codegen_const_declaration(const_decl_stmt),
+        Statement::Expression(expr) => Ok(format!("\t{};\n", codegen_expression(expr)?)),
+        Statement::If(conditionals) => Ok(format!("\t{}\n", codegen_if(conditionals)?)),
+        Statement::Loop(loops) => Ok(format!("\t{}\n", codegen_loop(loops)?)),
+        // the C block ends the lifetime of the bindings declared in it
+        Statement::Block(block) => Ok(format!("\t{{\n{}\t}}\n", indent(codegen_block(block)?))),
+        Statement::Break(break_stmt) => codegen_break(break_stmt),
+        Statement::Continue(continue_stmt) => codegen_continue(continue_stmt),
     }
 }
 
-fn codegen_return_statement(statement: ReturnStmts) -> Result<String, CodegenError> {
+/// Lowers a loop to a C loop. C can only leave the innermost loop, so the
+/// loop gets `goto` labels when it has a label of its own, and every name
+/// it needs is made unique with the offset of the loop in the source.
+fn codegen_loop(loops: Loops) -> Result<String, Diagnostic> {
+    let id = loops.span.start;
+
+    let value = match loops.value_type.as_deref() {
+        None | Some("()") => "".to_string(),
+        Some(name) => {
+            let native = to_native_type(name).ok_or_else(|| unknown_type(name, loops.span))?;
+            format!("{native} __loop_value_{id};\n\t")
+        }
+    };
+
+    let head = match loops.kind {
+        LoopKinds::Infinite => "for (;;)".to_string(),
+        LoopKinds::While(cond) => format!("while ({})", codegen_expression(cond)?),
+        // the end of the range is evaluated once, before the first iteration
+        LoopKinds::For(range) => {
+            let var_type = range.var_type.as_deref().unwrap_or("i32");
+            let native =
+                to_native_type(var_type).ok_or_else(|| unknown_type(var_type, loops.span))?;
+            let var = range.var;
+            let start = codegen_expression(range.start)?;
+            let end = codegen_expression(range.end)?;
+            format!(
+                "for ({native} {var} = {start}, __end_{id} = {end}; {var} < __end_{id}; {var}++)"
+            )
+        }
+    };
+
+    let body = indent(codegen_block(loops.scope)?);
+    let (continue_label, break_label) = match loops.label {
+        Some(_) => (
+            format!("\t\t__continue_{id}:;\n"),
+            format!("\n\t__break_{id}:;"),
+        ),
+        None => ("".to_string(), "".to_string()),
+    };
+
+    Ok(format!(
+        "{value}{head} {{\n{body}{continue_label}\t}}{break_label}"
+    ))
+}
+
+/// A `loop` used as a value, the loop runs inside a GNU statement
+/// expression that evaluates to the value it was left with.
+fn codegen_loop_expression(loops: Loops) -> Result<String, Diagnostic> {
+    let id = loops.span.start;
+    let result = match loops.value_type.as_deref() {
+        None | Some("()") => "".to_string(),
+        Some(_) => format!("\t__loop_value_{id};\n"),
+    };
+
+    let block = indent(format!("\t{}\n{result}", codegen_loop(loops)?));
+    Ok(format!("({{\n{block}\t}})"))
+}
+
+fn codegen_break(break_stmt: BreakStmts) -> Result<String, Diagnostic> {
+    let id = break_stmt
+        .target
+        .ok_or_else(|| Diagnostic::error("`break` outside of a loop", break_stmt.span))?
+        .start;
+
+    let value = match break_stmt.value {
+        Some(value) => format!("\t__loop_value_{id} = {};\n", codegen_expression(value)?),
+        None => "".to_string(),
+    };
+
+    let jump = match break_stmt.label {
+        Some(_) => format!("\tgoto __break_{id};\n"),
+        None => "\tbreak;\n".to_string(),
+    };
+
+    Ok(format!("{value}{jump}"))
+}
+
+fn codegen_continue(continue_stmt: ContinueStmts) -> Result<String, Diagnostic> {
+    let id = continue_stmt
+        .target
+        .ok_or_else(|| Diagnostic::error("`continue` outside of a loop", continue_stmt.span))?
+        .start;
+
+    Ok(match continue_stmt.label {
+        Some(_) => format!("\tgoto __continue_{id};\n"),
+        None => "\tcontinue;\n".to_string(),
+    })
+}
+
+fn codegen_if(conditionals: Conditionals) -> Result<String, Diagnostic> {
+    let cond = codegen_expression(conditionals.cond)?;
+    let then_block = indent(codegen_block(conditionals.then_scope)?);
+
+    let code = format!("if ({cond}) {{\n{then_block}\t}}");
+
+    Ok(match conditionals.else_branch {
+        Some(ElseBranch::ElseIf(else_if)) => format!("{code} else {}", codegen_if(*else_if)?),
+        Some(ElseBranch::Else(else_scope)) => {
+            let else_block = indent(codegen_block(else_scope)?);
+            format!("{code} else {{\n{else_block}\t}}")
+        }
+        None => code,
+    })
+}
+
+fn codegen_return_statement(statement: ReturnStmts) -> Result<String, Diagnostic> {
     let expr = codegen_expression(statement.ret)?;
     Ok(format!("\treturn {};\n", expr))
 }
 
-fn codegen_assignment(assignment_stmts: AssignmentStmts) -> Result<String, CodegenError> {
-    let ident = match assignment_stmts.lhs.token {
-        Token::Ident(ident) => ident,
-        _ => return Err(CodegenError),
-    };
+fn codegen_assignment(assignment_stmts: AssignmentStmts) -> Result<String, Diagnostic> {
+    let place = codegen_place(assignment_stmts.lhs)?;
+    let op = assignment_stmts.kind.operator();
 
-    let expr = codegen_expression(assignment_stmts.rhs)?;
+    Ok(match assignment_stmts.kind {
+        AssignmentKinds::Increment | AssignmentKinds::Decrement => format!("\t{place}{op};\n"),
+        _ => {
+            let expr = codegen_expression(assignment_stmts.rhs)?;
+            format!("\t{place} {op} {expr};\n")
+        }
+    })
+}
 
-    Ok(format!("\t{ident} = {expr};\n"))
+fn codegen_place(place: Place) -> Result<String, Diagnostic> {
+    match place {
+        Place::Variable(Tokens {
+            token: Token::Ident(ident),
+            ..
+        }) => Ok(ident),
+        Place::Variable(other) => Err(Diagnostic::error(
+            format!("cannot assign to {}", other.token),
+            other.span,
+        )
+        .with_note("only variables can be assigned to")),
+        Place::Field(base, field, _) => Ok(format!("{}.{field}", codegen_place(*base)?)),
+        Place::Index(base, index, _) => Ok(format!(
+            "{}[{}]",
+            codegen_place(*base)?,
+            codegen_expression(*index)?
+        )),
+        Place::Deref(base, _) => Ok(format!("(*{})", codegen_place(*base)?)),
+    }
 }
 
-fn codegen_mut_declaration(decl_stmts: DeclarationStmts) -> Result<String, CodegenError> {
-    let declaration = codegen_declaration(decl_stmts.lhs)?;
+fn codegen_mut_declaration(decl_stmts: DeclarationStmts) -> Result<String, Diagnostic> {
+    let declaration = codegen_declaration(&decl_stmts.lhs)?;
 
     let expr = codegen_expression(decl_stmts.rhs)?;
 
     Ok(format!("\t{declaration} = {expr};\n"))
 }
 
-fn codegen_const_declaration(decl_stmts: DeclarationStmts) -> Result<String, CodegenError> {
-    let declaration = codegen_declaration(decl_stmts.lhs)?;
+fn codegen_const_declaration(decl_stmts: DeclarationStmts) -> Result<String, Diagnostic> {
+    let declaration = codegen_declaration(&decl_stmts.lhs)?;
     let expr = codegen_expression(decl_stmts.rhs)?;
 
-    Ok(format!("\t{declaration} = {expr};\n"))
+    Ok(format!("\tconst {declaration} = {expr};\n"))
 }
 
-fn codegen_declaration(decl: Declarations) -> Result<String, CodegenError> {
-    let data_type = to_native_type(&decl.data_type).ok_or(CodegenError)?;
+fn codegen_declaration(decl: &Declarations) -> Result<String, Diagnostic> {
+    // semantic analysis gives every declaration a type or reports it
+    let data_type = decl.data_type.as_deref().ok_or_else(|| {
+        Diagnostic::error(
+            format!("type annotations needed for `{}`", decl.ident),
+            decl.ident_span,
+        )
+    })?;
+    let data_type =
+        to_native_type(data_type).ok_or_else(|| unknown_type(data_type, decl.data_type_span))?;
 
     Ok(format!("{} {}", data_type, decl.ident))
 }
 
-fn codegen_expression(expr: Expression) -> Result<String, CodegenError> {
+fn codegen_expression(expr: Expression) -> Result<String, Diagnostic> {
     Ok(match expr {
         Expression::Value(tok) => match tok.token {
             Token::Ident(name) => name,
-            Token::IntLit(num) => num.to_string(),
-            _ => return Err(CodegenError),
+            Token::IntLit(num, suffix) => codegen_int_literal(num, suffix.as_deref()),
+            Token::FloatLit(number, suffix) => codegen_float_literal(&number, suffix.as_deref()),
+            Token::CharLit(character) => codegen_char_literal(character),
+            Token::StrLit(contents) => codegen_str_literal(&contents),
+            other => {
+                return Err(Diagnostic::error(
+                    format!("expected a value, found {other}"),
+                    tok.span,
+                ))
+            }
         },
-        Expression::Addition(l_expr, r_expr) => {
-            let l_expr = codegen_expression(*l_expr)?;
-            let r_expr = codegen_expression(*r_expr)?;
-            format!("{l_expr} + {r_expr}")
+        Expression::Addition(l_expr, r_expr) => codegen_binary_expression(*l_expr, "+", *r_expr)?,
+        Expression::Subtraction(l_expr, r_expr) => {
+            codegen_binary_expression(*l_expr, "-", *r_expr)?
+        }
+        Expression::Multiplication(l_expr, r_expr) => {
+            codegen_binary_expression(*l_expr, "*", *r_expr)?
+        }
+        Expression::Division(l_expr, r_expr) => codegen_binary_expression(*l_expr, "/", *r_expr)?,
+        Expression::Equal(l_expr, r_expr) => codegen_binary_expression(*l_expr, "==", *r_expr)?,
+        Expression::NotEqual(l_expr, r_expr) => codegen_binary_expression(*l_expr, "!=", *r_expr)?,
+        Expression::Less(l_expr, r_expr) => codegen_binary_expression(*l_expr, "<", *r_expr)?,
+        Expression::LessEqual(l_expr, r_expr) => codegen_binary_expression(*l_expr, "<=", *r_expr)?,
+        Expression::Greater(l_expr, r_expr) => codegen_binary_expression(*l_expr, ">", *r_expr)?,
+        Expression::GreaterEqual(l_expr, r_expr) => {
+            codegen_binary_expression(*l_expr, ">=", *r_expr)?
+        }
+        Expression::And(l_expr, r_expr) => codegen_binary_expression(*l_expr, "&&", *r_expr)?,
+        Expression::Or(l_expr, r_expr) => codegen_binary_expression(*l_expr, "||", *r_expr)?,
+        Expression::Not(expr, _) => format!("(!{})", codegen_expression(*expr)?),
+        Expression::Negation(expr, _) => match *expr {
+            // `9223372036854775808` does not fit any signed C type
+            Expression::Value(Tokens {
+                token: Token::IntLit(num, Some(ref suffix)),
+                ..
+            }) if suffix == "i64" && num == i64::MIN.unsigned_abs() => "INT64_MIN".to_string(),
+            expr => format!("(-{})", codegen_expression(expr)?),
+        },
+        // `true` and `false` come from <stdbool.h>
+        Expression::BoolLit(value, _) => value.to_string(),
+        Expression::Cast(cast) => {
+            let native_type = to_native_type(&cast.data_type)
+                .ok_or_else(|| unknown_type(&cast.data_type, cast.data_type_span))?;
+            format!("(({native_type}){})", codegen_expression(cast.expr)?)
+        }
+        Expression::If(conditionals) => codegen_if_expression(*conditionals)?,
+        Expression::Loop(loops) => codegen_loop_expression(*loops)?,
+        Expression::Block(block) => codegen_scope_value(*block)?,
+        Expression::FnCall(name, fn_args) => {
+            let args = fn_args
+                .args
+                .into_iter()
+                .map(codegen_expression)
+                .collect::<Result<Vec<String>, Diagnostic>>()?
+                .join(", ");
+            format!("{name}({args})")
         }
     })
 }
 
+/// An `if` used as a value becomes a ternary, branches that need statements
+/// before their value become GNU statement expressions.
+fn codegen_if_expression(conditionals: Conditionals) -> Result<String, Diagnostic> {
+    let span = conditionals.span;
+    let cond = codegen_expression(conditionals.cond)?;
+    let then_value = codegen_scope_value(conditionals.then_scope)?;
+
+    let else_value = match conditionals.else_branch {
+        Some(ElseBranch::ElseIf(else_if)) => codegen_if_expression(*else_if)?,
+        Some(ElseBranch::Else(else_scope)) => codegen_scope_value(else_scope)?,
+        None => {
+            return Err(Diagnostic::error(
+                "`if` without an `else` can not be used as a value",
+                span,
+            ))
+        }
+    };
+
+    Ok(format!("({cond} ? {then_value} : {else_value})"))
+}
+
+fn codegen_scope_value(scope: Scope) -> Result<String, Diagnostic> {
+    if scope.stmts.is_empty() && scope.ret.is_none() {
+        if let Some(value) = scope.value {
+            return codegen_expression(*value);
+        }
+    }
+
+    let block = indent(codegen_block(scope)?);
+    Ok(format!("({{\n{block}\t}})"))
+}
+
+/// Every binary expression is wrapped in parentheses so the tree built by the
+/// parser is emitted as is, regardless of C's own precedence rules.
+fn codegen_binary_expression(
+    l_expr: Expression,
+    op: &str,
+    r_expr: Expression,
+) -> Result<String, Diagnostic> {
+    let l_expr = codegen_expression(l_expr)?;
+    let r_expr = codegen_expression(r_expr)?;
+    Ok(format!("({l_expr} {op} {r_expr})"))
+}
+
+/// C integer constants without a suffix are `int` or whatever signed type
+/// fits, 64 bit and unsigned literals keep their type with a suffix.
+fn codegen_int_literal(num: u64, suffix: Option<&str>) -> String {
+    match suffix {
+        Some("i64") => format!("{num}ll"),
+        Some("u64") => format!("{num}ull"),
+        Some("u8" | "u16" | "u32") => format!("{num}u"),
+        _ => num.to_string(),
+    }
+}
+
+/// C needs a `.` or an exponent to make `1f32` a float, and an `f` suffix
+/// for `float` constants.
+fn codegen_float_literal(number: &str, suffix: Option<&str>) -> String {
+    let number = if number.contains(['.', 'e', 'E']) {
+        number.to_string()
+    } else {
+        format!("{number}.0")
+    };
+    match suffix {
+        Some("f32") => format!("{number}f"),
+        _ => number,
+    }
+}
+
+/// `char` is a 32 bit unicode scalar value in C, printable ascii stays
+/// readable as a C character constant.
+fn codegen_char_literal(character: char) -> String {
+    match character {
+        ' '..='~' if !matches!(character, '\'' | '\\') => format!("'{character}'"),
+        _ => format!("0x{:x}u", u32::from(character)),
+    }
+}
+
+/// Every byte that is not plain printable ascii becomes an octal escape, so
+/// the contents can never end the C string early or form a trigraph.
+fn codegen_str_literal(contents: &str) -> String {
+    let escaped: String = contents
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if !matches!(byte, b'"' | b'\\' | b'?') => char::from(byte).to_string(),
+            _ => format!("\\{byte:03o}"),
+        })
+        .collect();
+    format!("((__str){{\"{escaped}\", {}}})", contents.len())
+}
+
 fn to_native_type(t: &str) -> Option<&str> {
     Some(match t {
-        "i32" => "int",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "f32" => "float",
+        "f64" => "double",
         "bool" => "bool",
-        "char" => "char",
+        "char" => "uint32_t",
+        "str" => "__str",
         _ => return None,
     })
 }
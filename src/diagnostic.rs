@@ -0,0 +1,147 @@
+use crate::source_map::{FileId, SourceMap};
+use std::fmt::Display;
+
+/// Byte range `start..end` into the source of the file `file_id`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file_id: FileId, start: usize, end: usize) -> Self {
+        Self {
+            file_id,
+            start,
+            end,
+        }
+    }
+
+    /// Smallest span covering both `self` and `other`, which must be in the
+    /// same file.
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            file_id: self.file_id,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Empty span right after `self`, used to point at missing code.
+    pub fn after(self) -> Self {
+        Self {
+            file_id: self.file_id,
+            start: self.end,
+            end: self.end,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl ToString, span: Span) -> Self {
+        Self::new(Severity::Error, message, span)
+    }
+
+    pub fn warning(message: impl ToString, span: Span) -> Self {
+        Self::new(Severity::Warning, message, span)
+    }
+
+    fn new(severity: Severity, message: impl ToString, span: Span) -> Self {
+        Self {
+            severity,
+            span,
+            message: message.to_string(),
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl ToString) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl ToString) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Renders the diagnostic rustc style, quoting the line the span starts
+    /// on and underlining the spanned code with carets.
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let (line_start, line) = source_map.line(self.span);
+        let position = source_map.line_column(self.span);
+        let (line_number, column) = (position.line + 1, position.column + 1);
+
+        // only the part of the span on the quoted line is underlined
+        let line_span = Span {
+            end: self.span.end.min(line_start + line.len()),
+            ..self.span
+        };
+        let caret_count = source_map.excerpt(line_span).chars().count().max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let indent: String = line
+            .chars()
+            .take(position.column)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let mut out = format!(
+            "{}: {}\n{gutter}--> {}:{line_number}:{column}\n{gutter} |\n{line_number} | {line}\n{gutter} | {indent}{}\n",
+            self.severity,
+            self.message,
+            source_map.path(self.span.file_id).display(),
+            "^".repeat(caret_count),
+        );
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            out += &format!("{gutter} |\n");
+        }
+        for note in &self.notes {
+            out += &format!("{gutter} = note: {note}\n");
+        }
+        if let Some(help) = &self.help {
+            out += &format!("{gutter} = help: {help}\n");
+        }
+
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} at {}..{}",
+            self.severity, self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for Diagnostic {}
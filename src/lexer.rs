@@ -1,339 +1,619 @@
+use crate::{
+    diagnostic::{Diagnostic, Span},
+    source_map::FileId,
+};
 use std::{
+    collections::VecDeque,
     fmt::Display,
-    io::{BufRead, BufReader},
+    io::{BufReader, Bytes, Read},
 };
 
-#[derive(Debug)]
-pub struct LexerError {
-    line: usize,
-    col: Option<usize>,
-    reason: String,
+/// Lexes `read` as it is read, only the characters needed to decide on the
+/// current token are ever buffered. Tokens don't depend on the whitespace
+/// around them, `x+2` and `x + 2` lex the same.
+pub fn lexical_analysis<R: Read>(read: R, file_id: FileId) -> Result<Vec<Tokens>, Diagnostic> {
+    lex_all(&mut Cursor::new(read, file_id))
 }
 
-impl Display for LexerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Error at {}:{}\n\t{}",
-            self.line,
-            self.col.map(|n| n.to_string()).unwrap_or("?".to_string()),
-            self.reason
-        )
+/// Tokens that keep everything between them, see `lossless_analysis`.
+#[derive(Debug, Clone)]
+pub struct LosslessTokens {
+    pub tokens: Vec<Tokens>,
+    /// whitespace and comments after the last token
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+/// Lexes like `lexical_analysis` but keeps the text of every token and the
+/// whitespace and comments before it, the source can be rebuilt exactly from
+/// the result. See `parser::cst`.
+pub fn lossless_analysis<R: Read>(read: R, file_id: FileId) -> Result<LosslessTokens, Diagnostic> {
+    let mut cursor = Cursor::new(read, file_id);
+    cursor.recorded = Some(String::new());
+
+    let tokens = lex_all(&mut cursor)?;
+    Ok(LosslessTokens {
+        tokens,
+        trailing_trivia: std::mem::take(&mut cursor.trivia),
+    })
+}
+
+fn lex_all<R: Read>(cursor: &mut Cursor<R>) -> Result<Vec<Tokens>, Diagnostic> {
+    let mut tokens = Vec::<Tokens>::new();
+
+    loop {
+        match lex_token(cursor) {
+            Ok(Some(token)) => tokens.push(token),
+            Ok(None) => break,
+            // a broken stream explains whatever went wrong after it
+            Err(diagnostic) => return Err(cursor.error.take().unwrap_or(diagnostic)),
+        }
+    }
+
+    match cursor.error.take() {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(tokens),
     }
 }
 
-impl std::error::Error for LexerError {}
-
-pub fn lexical_analysis<R: std::io::Read>(read: R) -> Result<Vec<Tokens>, LexerError> {
-    let buf_reader = BufReader::new(read);
-    let tokens = buf_reader
-        .lines()
-        .enumerate()
-        .map(|(line_number, line)| {
-            let line = match line {
-                Ok(l) => l,
-                Err(e) => {
-                    return Err(LexerError {
-                        line: line_number,
-                        col: None,
-                        reason: e.to_string(),
-                    })
-                }
-            };
-
-            match lex_lines(line_number, line) {
-                LexLineResult::Ok(tok) => Ok(tok),
-                LexLineResult::Err {
-                    line_number,
-                    col,
-                    reason,
-                } => {
-                    return Err(LexerError {
-                        line: line_number,
-                        col: Some(col),
-                        reason,
-                    })
+/// Characters of a `Read` decoded from UTF-8 as the lexer asks for them,
+/// tracking the byte offset, line and column of the next one.
+struct Cursor<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    file_id: FileId,
+    /// decoded characters not consumed yet, with their byte offset
+    lookahead: VecDeque<(usize, char)>,
+    /// byte offset of the next character to decode
+    decoded_up_to: usize,
+    line: usize,
+    column: usize,
+    /// a read or decoding error, the source ends where it happened
+    error: Option<Diagnostic>,
+    /// every character consumed since the last `take_recorded`, only kept
+    /// when lexing losslessly
+    recorded: Option<String>,
+    /// whitespace and comments since the last token, only kept when lexing
+    /// losslessly
+    trivia: Vec<Trivia>,
+}
+
+impl<R: Read> Cursor<R> {
+    fn new(read: R, file_id: FileId) -> Self {
+        Self {
+            bytes: BufReader::new(read).bytes(),
+            file_id,
+            lookahead: VecDeque::new(),
+            decoded_up_to: 0,
+            line: 0,
+            column: 0,
+            error: None,
+            recorded: None,
+            trivia: vec![],
+        }
+    }
+
+    /// The text consumed since the last call, `None` when not recording.
+    fn take_recorded(&mut self) -> Option<String> {
+        self.recorded.as_mut().map(std::mem::take)
+    }
+
+    /// Keeps what was consumed since byte `start` as trivia of the next token.
+    fn push_trivia(&mut self, kind: TriviaKind, start: usize) {
+        if let Some(text) = self.take_recorded() {
+            let span = self.span(start, self.offset());
+            self.trivia.push(Trivia { kind, text, span });
+        }
+    }
+
+    /// Byte offset of the next character, the length of the source once
+    /// everything has been consumed.
+    fn offset(&self) -> usize {
+        self.lookahead
+            .front()
+            .map_or(self.decoded_up_to, |(offset, _)| *offset)
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.file_id, start, end)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    /// The character `n` places after the next one, without consuming any.
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        while self.lookahead.len() <= n {
+            let decoded = self.decode()?;
+            self.lookahead.push_back(decoded);
+        }
+        Some(self.lookahead[n].1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        self.peek()?;
+        let (_, character) = self.lookahead.pop_front()?;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(character);
+        }
+
+        if character == '\n' {
+            self.line += 1;
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+        Some(character)
+    }
+
+    /// Consumes the next character when it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        self.bump_if(|character| character == expected).is_some()
+    }
+
+    fn bump_if(&mut self, predicate: impl Fn(char) -> bool) -> Option<char> {
+        match self.peek() {
+            Some(character) if predicate(character) => self.bump(),
+            _ => None,
+        }
+    }
+
+    fn decode(&mut self) -> Option<(usize, char)> {
+        if self.error.is_some() {
+            return None;
+        }
+        let start = self.decoded_up_to;
+
+        let mut bytes = [0; 4];
+        let mut width = 0;
+        let decoded = loop {
+            match self.bytes.next() {
+                Some(Ok(byte)) => bytes[width] = byte,
+                Some(Err(e)) => {
+                    self.error = Some(Diagnostic::error(e.to_string(), self.span(start, start)));
+                    return None;
                 }
+                // the stream ending in the middle of a character is invalid
+                None if width == 0 => return None,
+                None => break None,
             }
-        })
-        .collect::<Result<Vec<Vec<Tokens>>, LexerError>>()?
-        .concat();
+            width += 1;
 
-    Ok(tokens)
-}
+            match std::str::from_utf8(&bytes[..width]) {
+                Ok(decoded) => break decoded.chars().next(),
+                // an incomplete character, more bytes are needed
+                Err(e) if e.error_len().is_none() && width < 4 => (),
+                Err(_) => break None,
+            }
+        };
 
-pub enum LexLineResult {
-    Ok(Vec<Tokens>),
-    Err {
-        line_number: usize,
-        col: usize,
-        reason: String,
-    },
+        match decoded {
+            Some(character) => {
+                self.decoded_up_to += width;
+                Some((start, character))
+            }
+            None => {
+                self.error = Some(Diagnostic::error(
+                    "stream did not contain valid UTF-8",
+                    self.span(start, start),
+                ));
+                None
+            }
+        }
+    }
 }
 
-pub fn lex_lines(line_number: usize, line: String) -> LexLineResult {
-    let mut char_buf = String::new();
-    let mut tokens = Vec::<Tokens>::new();
-    let mut char_iter = line.chars().enumerate().peekable();
+/// Lexes the next token, skipping the whitespace and comments before it.
+/// `None` at the end of the source.
+fn lex_token<R: Read>(cursor: &mut Cursor<R>) -> Result<Option<Tokens>, Diagnostic> {
+    loop {
+        let whitespace_start = cursor.offset();
+        if cursor.bump_if(char::is_whitespace).is_some() {
+            while cursor.bump_if(char::is_whitespace).is_some() {}
+            cursor.push_trivia(TriviaKind::Whitespace, whitespace_start);
+        }
 
-    while let Some((column, character)) = char_iter.next() {
-        let peeked_char = char_iter.peek();
-        match character {
-            // look for other types of whitespace
-            ' ' | '\t' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
+        let (line, column, start) = (cursor.line, cursor.column, cursor.offset());
+        let Some(character) = cursor.bump() else {
+            return Ok(None);
+        };
+
+        // operators take the longest match, `<=` is never `<` then `=`
+        let token = match character {
+            '/' if cursor.eat('/') => match lex_line_comment(cursor) {
+                Some(doc) => Token::DocComment(doc),
+                None => {
+                    cursor.push_trivia(TriviaKind::LineComment, start);
+                    continue;
                 }
+            },
+            '/' if cursor.peek() == Some('*') => {
+                skip_block_comment(cursor, start)?;
+                cursor.push_trivia(TriviaKind::BlockComment, start);
+                continue;
             }
-            '=' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::Eq,
-                });
+            '/' if cursor.eat('=') => Token::FSlashEq,
+            '/' => Token::FSlash,
+            '=' if cursor.eat('=') => Token::EqEq,
+            '=' => Token::Eq,
+            '!' if cursor.eat('=') => Token::NotEq,
+            '!' => Token::Bang,
+            // `<<` and `>>` are only operators as part of `<<=` and `>>=`
+            '<' if cursor.peek() == Some('<') && cursor.peek_nth(1) == Some('=') => {
+                cursor.bump();
+                cursor.bump();
+                Token::ShlEq
             }
-            ';' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::Semi,
-                });
+            '<' if cursor.eat('=') => Token::LtEq,
+            '<' => Token::Lt,
+            '>' if cursor.peek() == Some('>') && cursor.peek_nth(1) == Some('=') => {
+                cursor.bump();
+                cursor.bump();
+                Token::ShrEq
             }
-            ':' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::Colon,
-                });
+            '>' if cursor.eat('=') => Token::GtEq,
+            '>' => Token::Gt,
+            '&' if cursor.eat('&') => Token::AndAnd,
+            '&' if cursor.eat('=') => Token::AndEq,
+            '&' => {
+                return Err(unknown_operator(cursor, '&', start)
+                    .with_help("use `&&` for a logical operator"))
             }
-            '+' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::Plus,
-                });
+            '|' if cursor.eat('|') => Token::OrOr,
+            '|' if cursor.eat('=') => Token::OrEq,
+            '|' => {
+                return Err(unknown_operator(cursor, '|', start)
+                    .with_help("use `||` for a logical operator"))
             }
-            '(' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::LParen,
-                });
+            '.' if cursor.eat('.') => Token::DotDot,
+            '.' => Token::Dot,
+            '-' if cursor.eat('>') => Token::Arrow,
+            '-' if cursor.eat('=') => Token::MinusEq,
+            // `--` only decrements right before a `;`, elsewhere it is two
+            // minuses and `a--b` is `a - -b`
+            '-' if cursor.peek() == Some('-') && ends_statement(cursor, 1) => {
+                cursor.bump();
+                Token::MinusMinus
             }
-            ')' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
-                }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::RParen,
-                });
+            '-' => Token::Minus,
+            '+' if cursor.eat('=') => Token::PlusEq,
+            '+' if cursor.eat('+') => Token::PlusPlus,
+            '+' => Token::Plus,
+            '*' if cursor.eat('=') => Token::MultEq,
+            '*' => Token::Mult,
+            '%' if cursor.eat('=') => Token::PercentEq,
+            '%' => {
+                return Err(unknown_operator(cursor, '%', start)
+                    .with_note("`%` is only supported as part of `%=`"))
+            }
+            '^' if cursor.eat('=') => Token::CaretEq,
+            '^' => {
+                return Err(unknown_operator(cursor, '^', start)
+                    .with_note("`^` is only supported as part of `^=`"))
             }
-            '{' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
+            ';' => Token::Semi,
+            ':' => Token::Colon,
+            ',' => Token::Comma,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '"' => Token::StrLit(lex_quoted(cursor, '"', start)?),
+            // `'a'` is a character, `'outer` a label
+            '\'' if starts_char_literal(cursor) => lex_char_literal(cursor, start)?,
+            'r' if starts_raw_string(cursor) => Token::StrLit(lex_raw_string(cursor, start)?),
+            '\'' => lex_word(cursor, '\'', start)?,
+            character if is_word_char(character) => lex_word(cursor, character, start)?,
+            character => {
+                let mut diagnostic = Diagnostic::error(
+                    format!("unknown character `{}`", character.escape_debug()),
+                    cursor.span(start, cursor.offset()),
+                );
+                if character.is_alphanumeric() {
+                    diagnostic = diagnostic
+                        .with_note("identifiers can only use ascii letters, digits and `_`");
                 }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::LBrace,
-                });
+                return Err(diagnostic);
+            }
+        };
+
+        return Ok(Some(Tokens {
+            token,
+            span: cursor.span(start, cursor.offset()),
+            line,
+            column,
+            written: cursor.take_recorded().map(|text| {
+                Box::new(Written {
+                    trivia: std::mem::take(&mut cursor.trivia),
+                    text,
+                })
+            }),
+        }));
+    }
+}
+
+fn unknown_operator<R: Read>(cursor: &Cursor<R>, operator: char, start: usize) -> Diagnostic {
+    Diagnostic::error(
+        format!("unknown operator `{operator}`"),
+        cursor.span(start, start + 1),
+    )
+}
+
+/// Characters of an identifier, keyword, label or number, the grammar's
+/// `ident` is ascii letters, digits and `_`.
+fn is_word_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_'
+}
+
+/// Lexes the identifier, keyword, label or number starting with `first`, up
+/// to the next whitespace or operator. The `.` and exponent sign of a float
+/// literal are part of the number.
+fn lex_word<R: Read>(
+    cursor: &mut Cursor<R>,
+    first: char,
+    start: usize,
+) -> Result<Token, Diagnostic> {
+    let mut word = String::from(first);
+
+    loop {
+        match cursor.peek() {
+            Some(character) if is_word_char(character) => word.push(character),
+            Some(character @ ('.' | '+' | '-'))
+                if continues_float_literal(&word, character, cursor.peek_nth(1)) =>
+            {
+                word.push(character)
+            }
+            _ => break,
+        }
+        cursor.bump();
+    }
+
+    match identify_token(word) {
+        IdentifyTokenResult::Tok(token) => Ok(token),
+        IdentifyTokenResult::ParseErr(e) => {
+            Err(Diagnostic::error(e, cursor.span(start, cursor.offset())))
+        }
+    }
+}
+
+/// Reads the rest of a `//` comment, the text of a `///` doc comment is
+/// returned and plain comments are dropped.
+fn lex_line_comment<R: Read>(cursor: &mut Cursor<R>) -> Option<String> {
+    let mut comment = String::new();
+    while let Some(character) = cursor.bump_if(|c| c != '\n') {
+        comment.push(character);
+    }
+
+    // `///` documents what follows it, `////` is a plain comment again
+    let doc = comment
+        .strip_prefix('/')
+        .filter(|doc| !doc.starts_with('/'))?;
+    Some(doc.trim_end_matches('\r').to_string())
+}
+
+/// Skips a block comment whose `/` at byte `open` was just consumed. Block
+/// comments nest, `/* /* */ */` is a single comment.
+fn skip_block_comment<R: Read>(cursor: &mut Cursor<R>, open: usize) -> Result<(), Diagnostic> {
+    cursor.bump();
+    let mut depth = 1;
+
+    while let Some(character) = cursor.bump() {
+        match (character, cursor.peek()) {
+            ('/', Some('*')) => {
+                cursor.bump();
+                depth += 1;
             }
-            '}' => {
-                if !char_buf.is_empty() {
-                    let loc = column - char_buf.len();
-                    let token = match identify_token(char_buf.to_owned()) {
-                        IdentifyTokenResult::Tok(tok) => tok,
-                        IdentifyTokenResult::ParseErr(e) => {
-                            return LexLineResult::Err {
-                                col: loc,
-                                line_number,
-                                reason: e.to_string(),
-                            }
-                        }
-                    };
-                    tokens.push(Tokens {
-                        line: line_number,
-                        loc,
-                        token,
-                    });
-                    char_buf = String::new();
+            ('*', Some('/')) => {
+                cursor.bump();
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
                 }
-                tokens.push(Tokens {
-                    line: line_number,
-                    loc: column,
-                    token: Token::RBrace,
-                });
             }
-            _ => {
-                char_buf.push(character);
+            _ => (),
+        }
+    }
+
+    Err(Diagnostic::error(
+        "unterminated block comment",
+        cursor.span(open, open + 2),
+    ))
+}
+
+/// A `'` starts a character literal rather than a label when it is followed
+/// by an escape, or by a word that is closed by another `'`. `'ab'` is a
+/// character literal too, so it gets reported as one.
+fn starts_char_literal<R: Read>(cursor: &mut Cursor<R>) -> bool {
+    match cursor.peek() {
+        Some('\\' | '\'') => true,
+        Some(_) => {
+            let mut n = 1;
+            while cursor
+                .peek_nth(n)
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            {
+                n += 1;
+            }
+            cursor.peek_nth(n) == Some('\'')
+        }
+        None => false,
+    }
+}
+
+fn lex_char_literal<R: Read>(cursor: &mut Cursor<R>, start: usize) -> Result<Token, Diagnostic> {
+    let contents = lex_quoted(cursor, '\'', start)?;
+
+    let mut chars = contents.chars();
+    match (chars.next(), chars.next()) {
+        (Some(character), None) => Ok(Token::CharLit(character)),
+        _ => Err(Diagnostic::error(
+            "character literals must contain exactly one character",
+            cursor.span(start, cursor.offset()),
+        )
+        .with_help("use `\"` for a string")),
+    }
+}
+
+/// Reads a quoted literal whose opening `quote` at byte `open` was just
+/// consumed, decoding escapes up to and including the closing quote.
+fn lex_quoted<R: Read>(
+    cursor: &mut Cursor<R>,
+    quote: char,
+    open: usize,
+) -> Result<String, Diagnostic> {
+    let mut contents = String::new();
+
+    loop {
+        let escape_start = cursor.offset();
+        match cursor.bump() {
+            Some('\\') => contents.extend(lex_escape(cursor, escape_start)?),
+            Some(character) if character == quote => return Ok(contents),
+            // a line break in the literal is a `\n` whatever the file uses
+            Some('\r') if cursor.peek() == Some('\n') => (),
+            Some(character) => contents.push(character),
+            None => break,
+        }
+    }
+
+    let kind = if quote == '"' { "string" } else { "character" };
+    Err(Diagnostic::error(
+        format!("unterminated {kind} literal"),
+        cursor.span(open, open + 1),
+    ))
+}
+
+/// True when the next non-blank character from `n` characters ahead on is
+/// a `;`.
+fn ends_statement<R: Read>(cursor: &mut Cursor<R>, mut n: usize) -> bool {
+    while matches!(cursor.peek_nth(n), Some(' ' | '\t')) {
+        n += 1;
+    }
+    cursor.peek_nth(n) == Some(';')
+}
+
+/// `r"` or `r#"` with any number of `#`, the `r` being the current character.
+fn starts_raw_string<R: Read>(cursor: &mut Cursor<R>) -> bool {
+    let mut n = 0;
+    while cursor.peek_nth(n) == Some('#') {
+        n += 1;
+    }
+    cursor.peek_nth(n) == Some('"')
+}
+
+/// Reads a raw string whose `r` at byte `start` was just consumed, up to a
+/// `"` followed by as many `#` as it opened with. Nothing is escaped in a raw
+/// string.
+fn lex_raw_string<R: Read>(cursor: &mut Cursor<R>, start: usize) -> Result<String, Diagnostic> {
+    let mut hashes = 0;
+    while cursor.eat('#') {
+        hashes += 1;
+    }
+    cursor.bump();
+
+    let mut contents = String::new();
+    while let Some(character) = cursor.bump() {
+        match character {
+            '"' => {
+                let mut closing = 0;
+                while closing < hashes && cursor.eat('#') {
+                    closing += 1;
+                }
+                if closing == hashes {
+                    return Ok(contents);
+                }
+                contents.push('"');
+                contents.extend(std::iter::repeat_n('#', closing));
             }
+            '\r' if cursor.peek() == Some('\n') => (),
+            character => contents.push(character),
         }
     }
-    if !char_buf.is_empty() {
-        let loc = line.len() - char_buf.len();
-        let token = match identify_token(char_buf.to_owned()) {
-            IdentifyTokenResult::Tok(tok) => tok,
-            IdentifyTokenResult::ParseErr(e) => {
-                return LexLineResult::Err {
-                    col: loc,
-                    line_number,
-                    reason: e.to_string(),
+
+    Err(Diagnostic::error(
+        "unterminated raw string",
+        cursor.span(start, start + 2 + hashes),
+    )
+    .with_help(format!("close it with `\"{}`", "#".repeat(hashes))))
+}
+
+/// Decodes the escape sequence whose `\` at byte `start` was just consumed.
+/// A `\` at the end of a line joins the next one, skipping its indentation,
+/// and decodes to nothing.
+fn lex_escape<R: Read>(cursor: &mut Cursor<R>, start: usize) -> Result<Option<char>, Diagnostic> {
+    let mut escape = String::from('\\');
+    let next = |cursor: &mut Cursor<R>, escape: &mut String| {
+        let character = cursor.bump()?;
+        escape.push(character);
+        Some(character)
+    };
+    let is_hex = |digits: &str| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit());
+
+    let (decoded, help) = match next(cursor, &mut escape) {
+        Some('n') => return Ok(Some('\n')),
+        Some('r') => return Ok(Some('\r')),
+        Some('t') => return Ok(Some('\t')),
+        Some('0') => return Ok(Some('\0')),
+        Some(character @ ('\\' | '\'' | '"')) => return Ok(Some(character)),
+        Some('\n' | '\r') => {
+            while cursor.bump_if(char::is_whitespace).is_some() {}
+            return Ok(None);
+        }
+        Some('x') => {
+            let digits: String = (0..2).filter_map(|_| next(cursor, &mut escape)).collect();
+            let decoded = u8::from_str_radix(&digits, 16)
+                .ok()
+                .filter(|byte| is_hex(&digits) && byte.is_ascii())
+                .map(char::from);
+            (decoded, "`\\x` takes two hex digits, up to `7f`")
+        }
+        Some('u') => {
+            let braced = next(cursor, &mut escape) == Some('{');
+            let mut digits = String::new();
+            if braced {
+                while let Some(character) = next(cursor, &mut escape).filter(|c| *c != '}') {
+                    digits.push(character);
                 }
             }
-        };
+            let decoded = u32::from_str_radix(&digits, 16)
+                .ok()
+                .filter(|_| braced && is_hex(&digits) && digits.len() <= 6)
+                .and_then(char::from_u32);
+            (
+                decoded,
+                "`\\u{...}` takes one to six hex digits of a unicode scalar value",
+            )
+        }
+        _ => (
+            None,
+            "the escapes are `\\n \\r \\t \\0 \\\\ \\' \\\"`, `\\x7f` and `\\u{1F600}`",
+        ),
+    };
 
-        tokens.push(Tokens {
-            line: line_number,
-            loc,
-            token,
-        });
+    decoded.map(Some).ok_or_else(|| {
+        let span = cursor.span(start, start + escape.len());
+        Diagnostic::error(format!("invalid escape `{escape}`"), span).with_help(help)
+    })
+}
+
+/// `.` in `1.5` and the sign in `2e-3` are part of the number in
+/// `char_buf` rather than operators, when a digit follows them. Numbers with
+/// a base prefix are never floats.
+fn continues_float_literal(char_buf: &str, character: char, next: Option<char>) -> bool {
+    if !char_buf.starts_with(|c: char| c.is_ascii_digit())
+        || ["0x", "0o", "0b"]
+            .iter()
+            .any(|prefix| char_buf.starts_with(prefix))
+        || !next.is_some_and(|next| next.is_ascii_digit())
+    {
+        return false;
+    }
+
+    match character {
+        '.' => !char_buf.contains(['.', 'e', 'E']),
+        '+' | '-' => char_buf.ends_with(['e', 'E']),
+        _ => false,
     }
-    return LexLineResult::Ok(tokens);
 }
 
 enum IdentifyTokenResult {
@@ -347,30 +627,166 @@ fn identify_token(lit: String) -> IdentifyTokenResult {
         "mut" => Token::Mut,
         "return" => Token::Return,
         "fn" => Token::Fn,
-        "->" => Token::Arrow,
-        lit => {
-            // ident or literal
-            let first = lit.chars().nth(0).unwrap();
-
-            match first {
-                '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' | '0' | '-' => {
-                    let num: i32 = match first.to_string().parse::<i32>() {
-                        Ok(n) => n,
-                        Err(e) => return IdentifyTokenResult::ParseErr(e.to_string()),
-                    };
-                    Token::IntLit(num)
-                }
-                _ => Token::Ident(lit.to_string()),
-            }
+        "if" => Token::If,
+        "else" => Token::Else,
+        "while" => Token::While,
+        "for" => Token::For,
+        "in" => Token::In,
+        "loop" => Token::Loop,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
+        "as" => Token::As,
+        "true" => Token::BoolLit(true),
+        "false" => Token::BoolLit(false),
+        "'" => {
+            return IdentifyTokenResult::ParseErr(
+                "expected a label name or a character literal after `'`".to_string(),
+            )
+        }
+        lit if lit.starts_with('\'') => Token::Label(lit[1..].to_string()),
+        lit if lit.starts_with(|c: char| c.is_ascii_digit()) => {
+            return identify_number_literal(lit)
         }
+        lit => Token::Ident(lit.to_string()),
     })
 }
 
+/// Splits a number into its digits and an optional type suffix as in `255u8`
+/// or `1.5f32`. `0x`, `0o` and `0b` prefixes give hexadecimal, octal and
+/// binary integers, and `_` can separate digits anywhere after the first.
+fn identify_number_literal(lit: &str) -> IdentifyTokenResult {
+    let (radix, digits) = match lit.get(..2) {
+        Some("0x") => (16, &lit[2..]),
+        Some("0o") => (8, &lit[2..]),
+        Some("0b") => (2, &lit[2..]),
+        _ => (10, lit),
+    };
+
+    // hex digits would swallow an `e` exponent, and `f` is a hex digit
+    let number_end = digits
+        .find(|c: char| match radix {
+            16 => !c.is_ascii_hexdigit() && c != '_',
+            10 => !c.is_ascii_digit() && !matches!(c, '_' | '.' | 'e' | 'E' | '+' | '-'),
+            _ => !c.is_ascii_digit() && c != '_',
+        })
+        .unwrap_or(digits.len());
+    let (number, suffix) = digits.split_at(number_end);
+
+    if radix == 10 && (number.contains(['.', 'e', 'E']) || suffix.starts_with('f')) {
+        return identify_float_literal(lit, &number.replace('_', ""), suffix);
+    }
+
+    let base = match radix {
+        16 => "hexadecimal",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    };
+    let number = number.replace('_', "");
+    if number.is_empty() {
+        return IdentifyTokenResult::ParseErr(format!("{base} literal `{lit}` has no digits"));
+    }
+    if let Some(digit) = number.chars().find(|c| !c.is_digit(radix)) {
+        return IdentifyTokenResult::ParseErr(format!(
+            "invalid digit `{digit}` in {base} literal `{lit}`"
+        ));
+    }
+    let num = match u64::from_str_radix(&number, radix) {
+        Ok(n) => n,
+        Err(_) => {
+            return IdentifyTokenResult::ParseErr(format!(
+                "integer literal `{lit}` is too large, the largest is {}",
+                u64::MAX
+            ))
+        }
+    };
+
+    let suffix = match suffix {
+        "" => None,
+        "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" => Some(suffix.to_string()),
+        _ => {
+            return IdentifyTokenResult::ParseErr(format!(
+                "invalid suffix `{suffix}` for integer literal `{lit}`"
+            ))
+        }
+    };
+    IdentifyTokenResult::Tok(Token::IntLit(num, suffix))
+}
+
+/// `number` is kept as written, the C compiler rounds it like we would.
+fn identify_float_literal(lit: &str, number: &str, suffix: &str) -> IdentifyTokenResult {
+    if number.parse::<f64>().is_err() {
+        return IdentifyTokenResult::ParseErr(format!("invalid float literal `{lit}`"));
+    }
+
+    let suffix = match suffix {
+        "" => None,
+        "f32" | "f64" => Some(suffix.to_string()),
+        _ => {
+            return IdentifyTokenResult::ParseErr(format!(
+                "invalid suffix `{suffix}` for float literal `{lit}`"
+            ))
+        }
+    };
+    IdentifyTokenResult::Tok(Token::FloatLit(number.to_string(), suffix))
+}
+
 #[derive(Debug, Clone)]
 pub struct Tokens {
     pub token: Token,
+    pub span: Span,
     line: usize,
-    loc: usize,
+    column: usize,
+    /// only kept by `lossless_analysis`, boxed so tokens stay small
+    written: Option<Box<Written>>,
+}
+
+/// A token as it is written in the source.
+#[derive(Debug, Clone)]
+struct Written {
+    /// whitespace and comments before the token
+    trivia: Vec<Trivia>,
+    text: String,
+}
+
+impl Tokens {
+    /// Line the token starts on, counted from 0 like `SourceMap::line_column`.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column the token starts at in characters, counted from 0.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Whitespace and comments before the token, empty unless it was lexed
+    /// with `lossless_analysis`.
+    pub fn trivia(&self) -> &[Trivia] {
+        self.written.as_ref().map_or(&[], |written| &written.trivia)
+    }
+
+    /// The token as it is written in the source, `None` unless it was lexed
+    /// with `lossless_analysis`.
+    pub fn text(&self) -> Option<&str> {
+        self.written.as_ref().map(|written| written.text.as_str())
+    }
+}
+
+/// Whitespace or a comment, only kept by `lossless_analysis`. Doc comments
+/// are tokens rather than trivia.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -383,12 +799,19 @@ pub enum Token {
     Eq,
     Semi,
     Colon,
+    Comma,
     LParen,
     RParen,
-    IntLit(i32),
-    // float lit
-    // char lit
-    // string lit
+    /// value and type suffix, sema fills in the suffix of literals without one
+    IntLit(u64, Option<String>),
+    /// digits as written and type suffix, filled in by sema like `IntLit`
+    FloatLit(String, Option<String>),
+    BoolLit(bool),
+    CharLit(char),
+    /// contents with the escapes decoded
+    StrLit(String),
+    /// text of a `///` comment after the slashes
+    DocComment(String),
     // html lit
     // json lit
     LBrace,
@@ -398,4 +821,108 @@ pub enum Token {
     Mult,
     FSlash,
     Return,
+    If,
+    Else,
+    EqEq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    AndAnd,
+    OrOr,
+    Bang,
+    While,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    DotDot,
+    /// `'name` of a loop, without the quote
+    Label(String),
+    As,
+    PlusEq,
+    MinusEq,
+    MultEq,
+    FSlashEq,
+    PercentEq,
+    AndEq,
+    OrEq,
+    CaretEq,
+    ShlEq,
+    ShrEq,
+    PlusPlus,
+    MinusMinus,
+    Dot,
+    LBracket,
+    RBracket,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Const => write!(f, "`const`"),
+            Token::Mut => write!(f, "`mut`"),
+            Token::Ident(ident) => write!(f, "`{ident}`"),
+            Token::Fn => write!(f, "`fn`"),
+            Token::Arrow => write!(f, "`->`"),
+            Token::Eq => write!(f, "`=`"),
+            Token::Semi => write!(f, "`;`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::IntLit(num, suffix) => write!(f, "`{num}{}`", suffix.as_deref().unwrap_or("")),
+            Token::FloatLit(number, suffix) => {
+                write!(f, "`{number}{}`", suffix.as_deref().unwrap_or(""))
+            }
+            Token::BoolLit(value) => write!(f, "`{value}`"),
+            Token::CharLit(character) => write!(f, "`{character:?}`"),
+            Token::StrLit(contents) => write!(f, "`{contents:?}`"),
+            Token::DocComment(_) => write!(f, "a doc comment"),
+            Token::LBrace => write!(f, "`{{`"),
+            Token::RBrace => write!(f, "`}}`"),
+            Token::Plus => write!(f, "`+`"),
+            Token::Minus => write!(f, "`-`"),
+            Token::Mult => write!(f, "`*`"),
+            Token::FSlash => write!(f, "`/`"),
+            Token::Return => write!(f, "`return`"),
+            Token::If => write!(f, "`if`"),
+            Token::Else => write!(f, "`else`"),
+            Token::EqEq => write!(f, "`==`"),
+            Token::NotEq => write!(f, "`!=`"),
+            Token::Lt => write!(f, "`<`"),
+            Token::LtEq => write!(f, "`<=`"),
+            Token::Gt => write!(f, "`>`"),
+            Token::GtEq => write!(f, "`>=`"),
+            Token::AndAnd => write!(f, "`&&`"),
+            Token::OrOr => write!(f, "`||`"),
+            Token::Bang => write!(f, "`!`"),
+            Token::While => write!(f, "`while`"),
+            Token::For => write!(f, "`for`"),
+            Token::In => write!(f, "`in`"),
+            Token::Loop => write!(f, "`loop`"),
+            Token::Break => write!(f, "`break`"),
+            Token::Continue => write!(f, "`continue`"),
+            Token::DotDot => write!(f, "`..`"),
+            Token::Label(label) => write!(f, "`'{label}`"),
+            Token::As => write!(f, "`as`"),
+            Token::PlusEq => write!(f, "`+=`"),
+            Token::MinusEq => write!(f, "`-=`"),
+            Token::MultEq => write!(f, "`*=`"),
+            Token::FSlashEq => write!(f, "`/=`"),
+            Token::PercentEq => write!(f, "`%=`"),
+            Token::AndEq => write!(f, "`&=`"),
+            Token::OrEq => write!(f, "`|=`"),
+            Token::CaretEq => write!(f, "`^=`"),
+            Token::ShlEq => write!(f, "`<<=`"),
+            Token::ShrEq => write!(f, "`>>=`"),
+            Token::PlusPlus => write!(f, "`++`"),
+            Token::MinusMinus => write!(f, "`--`"),
+            Token::Dot => write!(f, "`.`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+        }
+    }
 }
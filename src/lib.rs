@@ -1,7 +1,10 @@
-#![feature(slice_split_once)]
-
 use clap::Parser;
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 #[derive(Parser, Debug, Clone)]
 pub struct Config {
@@ -13,17 +16,36 @@ pub struct Config {
 }
 
 pub mod codegen;
+pub mod diagnostic;
 pub mod lexer;
 pub mod parser;
+pub mod sema;
+pub mod source_map;
 
+/// A source file being compiled, every byte read through it is kept so
+/// diagnostics can quote the code they point at.
 #[derive(Debug)]
 pub struct CodeFile<R: Read> {
     path_buf: PathBuf,
     file: R,
+    source: Vec<u8>,
 }
 impl<R: Read> Read for CodeFile<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.file.read(buf)
+        let read = self.file.read(buf)?;
+        self.source.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: Read> CodeFile<R> {
+    pub fn path(&self) -> &Path {
+        &self.path_buf
+    }
+
+    /// The part of the file read so far.
+    pub fn source(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.source)
     }
 }
 
@@ -31,6 +53,10 @@ impl CodeFile<File> {
     pub fn new(path: impl ToString) -> Result<Self, std::io::Error> {
         let path_buf = PathBuf::from(path.to_string());
         let file = File::open(path.to_string())?;
-        Ok(Self { path_buf, file })
+        Ok(Self {
+            path_buf,
+            file,
+            source: vec![],
+        })
     }
 }
@@ -1,6 +1,9 @@
 use clap::Parser;
 use compiler_1::codegen::c_codegen;
+use compiler_1::diagnostic::{Diagnostic, Severity};
 use compiler_1::parser::parse_toplevel;
+use compiler_1::sema::semantic_analysis;
+use compiler_1::source_map::SourceMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::Write;
@@ -15,21 +18,40 @@ use compiler_1::Config;
 fn main() -> Result<ExitCode, Box<dyn Error>> {
     let config = Config::parse();
 
-    let file = CodeFile::new(&config.entry)?;
+    let mut source_map = SourceMap::default();
+    let file_id = source_map.add_file(&config.entry);
+    let mut file = CodeFile::new(&config.entry)?;
 
-    let tokens = lexical_analysis(file)?;
+    let tokens = lexical_analysis(&mut file, file_id);
+    // diagnostics can only quote what has been read
+    source_map.set_source(file_id, file.source());
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(diagnostic) => return Ok(report(&source_map, diagnostic)),
+    };
     println!("{:?}", tokens.clone());
 
-    let ast = parse_toplevel(tokens)?;
+    let (mut ast, diagnostics) = parse_toplevel(tokens);
+    if let Some(exit_code) = report_all(&source_map, diagnostics) {
+        return Ok(exit_code);
+    }
     println!("{:?}", ast);
 
-    let c_code = c_codegen(ast)?;
+    let diagnostics = semantic_analysis(&mut ast);
+    if let Some(exit_code) = report_all(&source_map, diagnostics) {
+        return Ok(exit_code);
+    }
+
+    let c_code = match c_codegen(ast) {
+        Ok(c_code) => c_code,
+        Err(diagnostic) => return Ok(report(&source_map, diagnostic)),
+    };
     println!("\n\n{}", c_code);
 
     let out_path = PathBuf::from(config.outfile);
     let mut outfile = File::create(out_path)?;
 
-    let _ = outfile.write_all(c_code.as_bytes())?;
+    outfile.write_all(c_code.as_bytes())?;
 
     Command::new("gcc")
         .args(["./bin/main.c", "-o", "./bin/main"])
@@ -37,5 +59,27 @@ fn main() -> Result<ExitCode, Box<dyn Error>> {
 
     Command::new("./bin/main").output()?;
 
-    return Ok(ExitCode::SUCCESS);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn report(source_map: &SourceMap, diagnostic: Diagnostic) -> ExitCode {
+    report_all(source_map, vec![diagnostic]).unwrap_or(ExitCode::FAILURE)
+}
+
+/// Prints every diagnostic, the exit code is only returned when one of them
+/// is an error, warnings alone don't stop the compilation.
+fn report_all(source_map: &SourceMap, diagnostics: Vec<Diagnostic>) -> Option<ExitCode> {
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(source_map));
+    }
+
+    if errors == 0 {
+        return None;
+    }
+    eprintln!("aborting due to {errors} previous error(s)");
+    Some(ExitCode::FAILURE)
 }
@@ -0,0 +1,207 @@
+use crate::lexer::{LosslessTokens, Token, Tokens, Trivia};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    /// the whole file
+    Root,
+    /// a function with its doc comments, signature and body
+    Function,
+    /// `{ ... }`
+    Block,
+    /// `( ... )` or `[ ... ]`
+    Group,
+    /// a statement with its `;`, or the value a block ends in
+    Statement,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Tokens),
+    /// whitespace and comments after the last token of the file
+    Trivia(Trivia),
+}
+
+/// Node of a concrete syntax tree, unlike the `Ast` it keeps every token and
+/// the whitespace and comments between them.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        Self {
+            kind,
+            children: vec![],
+        }
+    }
+
+    /// The source the node was built from, exactly as it was written.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text);
+        text
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.write_text(text),
+                SyntaxElement::Token(token) => {
+                    for trivia in token.trivia() {
+                        text.push_str(&trivia.text);
+                    }
+                    text.push_str(token.text().unwrap_or_default());
+                }
+                SyntaxElement::Trivia(trivia) => text.push_str(&trivia.text),
+            }
+        }
+    }
+}
+
+/// Builds the concrete syntax tree of a file lexed with `lossless_analysis`.
+/// The tree only groups tokens, so any token stream has one, and code that
+/// does not parse just ends up less structured. Errors are left to
+/// `parse_toplevel`.
+pub fn parse_cst(lossless: LosslessTokens) -> SyntaxNode {
+    let mut tokens = VecDeque::from(lossless.tokens);
+    let mut root = SyntaxNode::new(SyntaxKind::Root);
+
+    while let Some(token) = tokens.front() {
+        // doc comments go with the function after them
+        let starts_function = match token.token {
+            Token::Fn => true,
+            Token::DocComment(_) => tokens
+                .iter()
+                .find(|t| !matches!(t.token, Token::DocComment(_)))
+                .is_some_and(|t| t.token == Token::Fn),
+            _ => false,
+        };
+
+        let element = if starts_function {
+            SyntaxElement::Node(parse_function(&mut tokens))
+        } else {
+            bump(&mut tokens)
+        };
+        root.children.push(element);
+    }
+
+    root.children.extend(
+        lossless
+            .trailing_trivia
+            .into_iter()
+            .map(SyntaxElement::Trivia),
+    );
+    root
+}
+
+/// Pops the next token, which must exist.
+fn bump(tokens: &mut VecDeque<Tokens>) -> SyntaxElement {
+    SyntaxElement::Token(tokens.pop_front().expect("callers peek first"))
+}
+
+fn parse_function(tokens: &mut VecDeque<Tokens>) -> SyntaxNode {
+    let mut function = SyntaxNode::new(SyntaxKind::Function);
+    let mut seen_fn = false;
+
+    while let Some(token) = tokens.front() {
+        match token.token {
+            Token::LBrace => {
+                function
+                    .children
+                    .push(SyntaxElement::Node(parse_delimited(tokens)));
+                break;
+            }
+            Token::LParen | Token::LBracket => function
+                .children
+                .push(SyntaxElement::Node(parse_delimited(tokens))),
+            // a function missing its body ends where the next one starts
+            Token::Fn if seen_fn => break,
+            Token::Fn => {
+                seen_fn = true;
+                function.children.push(bump(tokens));
+            }
+            _ => function.children.push(bump(tokens)),
+        }
+    }
+
+    function
+}
+
+/// Parses a block or a group, up to the `}`, `)` or `]` that closes it. A
+/// block is split into statements.
+fn parse_delimited(tokens: &mut VecDeque<Tokens>) -> SyntaxNode {
+    let open = bump(tokens);
+    let (kind, close) = match &open {
+        SyntaxElement::Token(token) if token.token == Token::LBrace => {
+            (SyntaxKind::Block, Token::RBrace)
+        }
+        SyntaxElement::Token(token) if token.token == Token::LBracket => {
+            (SyntaxKind::Group, Token::RBracket)
+        }
+        _ => (SyntaxKind::Group, Token::RParen),
+    };
+    let mut node = SyntaxNode::new(kind);
+    node.children.push(open);
+
+    while let Some(token) = tokens.front() {
+        if token.token == close {
+            node.children.push(bump(tokens));
+            break;
+        }
+
+        let element = match (&token.token, kind) {
+            (Token::LBrace | Token::LParen | Token::LBracket, SyntaxKind::Group) => {
+                SyntaxElement::Node(parse_delimited(tokens))
+            }
+            // a `}` that closes nothing is kept as it is
+            (Token::RBrace | Token::RParen | Token::RBracket, _) => bump(tokens),
+            (_, SyntaxKind::Block) => SyntaxElement::Node(parse_statement(tokens)),
+            _ => bump(tokens),
+        };
+        node.children.push(element);
+    }
+
+    node
+}
+
+/// A statement ends after its `;`, or after the last block of an `if`,
+/// loop or block statement, which don't need one.
+fn parse_statement(tokens: &mut VecDeque<Tokens>) -> SyntaxNode {
+    let mut statement = SyntaxNode::new(SyntaxKind::Statement);
+    let block_like = tokens.front().is_some_and(|token| {
+        matches!(
+            token.token,
+            Token::If | Token::While | Token::For | Token::Loop | Token::Label(_) | Token::LBrace
+        )
+    });
+
+    while let Some(token) = tokens.front() {
+        match token.token {
+            Token::Semi => {
+                statement.children.push(bump(tokens));
+                break;
+            }
+            Token::RBrace | Token::RParen | Token::RBracket => break,
+            Token::LBrace | Token::LParen | Token::LBracket => {
+                let is_block = token.token == Token::LBrace;
+                statement
+                    .children
+                    .push(SyntaxElement::Node(parse_delimited(tokens)));
+
+                let continues = tokens
+                    .front()
+                    .is_some_and(|token| token.token == Token::Else);
+                if is_block && block_like && !continues {
+                    break;
+                }
+            }
+            _ => statement.children.push(bump(tokens)),
+        }
+    }
+
+    statement
+}
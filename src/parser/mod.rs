@@ -1,14 +1,9 @@
+use crate::diagnostic::{Diagnostic, Span};
 use crate::lexer::{Token, Tokens};
-use std::fmt::{Debug, Display};
+use std::fmt::Debug;
+use std::iter::Peekable;
 
-#[derive(Debug)]
-pub struct ParseError;
-impl std::error::Error for ParseError {}
-impl Display for ParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ast error")
-    }
-}
+pub mod cst;
 
 #[derive(Debug)]
 pub struct Ast {
@@ -21,200 +16,675 @@ impl Ast {
     }
 }
 
-pub fn parse_toplevel(tokens: impl IntoIterator<Item = Tokens>) -> Result<Ast, ParseError> {
-    let mut token_iter = tokens.into_iter();
+/// Recursive descent parser, one method per production of `grammar.md`.
+/// Methods return the first error of what they parse, and the statement or
+/// function it is in reports it and skips to where parsing can resume, so
+/// `diagnostics` collects every error in the file.
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Tokens>>,
+    /// span of the last token taken, missing tokens are reported after it
+    prev: Span,
+    diagnostics: Vec<Diagnostic>,
+}
 
-    let mut ast = Ast::empty();
-    let mut token_buf = Vec::<Tokens>::new();
-    let mut brace_counter = 0;
-    while let Some(token) = token_iter.next() {
-        if brace_counter < 0 {
-            return Err(ParseError);
+impl Parser {
+    /// Doc comments are only kept in front of the `fn` they document, the
+    /// rest are reported here so the grammar never has to skip them.
+    fn new(tokens: impl IntoIterator<Item = Tokens>) -> Self {
+        let mut diagnostics = vec![];
+        let mut kept = vec![];
+        let mut doc_comments = vec![];
+        for token in tokens {
+            match token.token {
+                Token::DocComment(_) => doc_comments.push(token),
+                Token::Fn => {
+                    kept.append(&mut doc_comments);
+                    kept.push(token);
+                }
+                _ => {
+                    diagnostics.extend(
+                        doc_comments
+                            .drain(..)
+                            .map(|doc| unattached_doc_comment(&doc)),
+                    );
+                    kept.push(token);
+                }
+            }
         }
-        match token.token {
-            Token::Fn => {
-                if token_buf.len() > 0 {
-                    return Err(ParseError);
+        diagnostics.extend(doc_comments.iter().map(unattached_doc_comment));
+
+        Self {
+            tokens: kept.into_iter().peekable(),
+            prev: Span::default(),
+            diagnostics,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|t| &t.token)
+    }
+
+    fn at(&mut self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn bump(&mut self) -> Option<Tokens> {
+        let token = self.tokens.next()?;
+        self.prev = token.span;
+        Some(token)
+    }
+
+    /// Takes the next token if `predicate` accepts it.
+    fn bump_if(&mut self, predicate: impl FnOnce(&Token) -> bool) -> Option<Tokens> {
+        let token = self.tokens.next_if(|t| predicate(&t.token))?;
+        self.prev = token.span;
+        Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> Option<Tokens> {
+        self.bump_if(|t| t == token)
+    }
+
+    /// "expected X, found Y" at the next token, or right after the last one
+    /// at the end of the file.
+    fn expected(&mut self, expected: &str) -> Diagnostic {
+        match self.tokens.peek() {
+            Some(token) => unexpected_token(token, expected),
+            None => Diagnostic::error(format!("expected {expected}"), self.prev.after()),
+        }
+    }
+
+    /// Takes the next token and checks it is `expected`, a wrong token is
+    /// left for error recovery.
+    fn expect(&mut self, expected: Token) -> Result<Tokens, Diagnostic> {
+        match self.eat(&expected) {
+            Some(token) => Ok(token),
+            None => Err(self.expected(&expected.to_string())),
+        }
+    }
+
+    /// Takes the next token and checks it is an identifier naming `what`.
+    fn expect_ident(&mut self, what: &str) -> Result<(String, Span), Diagnostic> {
+        match self.bump_if(|t| matches!(t, Token::Ident(_))) {
+            Some(Tokens {
+                token: Token::Ident(ident),
+                span,
+                ..
+            }) => Ok((ident, span)),
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    /// A missing `;` is pointed at right after the statement it ends, which
+    /// is where it has to go, rather than at whatever follows.
+    fn expect_semi(&mut self) -> Result<(), Diagnostic> {
+        if self.eat(&Token::Semi).is_some() {
+            return Ok(());
+        }
+
+        let message = match self.peek() {
+            Some(token) => format!("expected `;`, found {token}"),
+            None => "expected `;`".to_string(),
+        };
+        let diagnostic = Diagnostic::error(message, self.prev.after());
+        Err(match self.peek() {
+            Some(Token::PlusPlus | Token::MinusMinus) => diagnostic
+                .with_note("`++` and `--` are statements of their own, they have no value"),
+            _ => diagnostic,
+        })
+    }
+
+    /// Skips the rest of a broken statement, up to and including its `;`, or
+    /// up to the `}` that closes the scope it is in.
+    fn recover_statement(&mut self) {
+        let mut brace_counter = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Semi if brace_counter == 0 => {
+                    self.bump();
+                    return;
                 }
-                token_buf.push(token);
+                Token::RBrace | Token::Fn if brace_counter == 0 => return,
+                Token::LBrace => brace_counter += 1,
+                Token::RBrace => brace_counter -= 1,
+                _ => {}
             }
-            Token::LBrace => {
-                brace_counter += 1;
-                token_buf.push(token);
+            self.bump();
+        }
+    }
+
+    /// Skips to the next `fn`, over any blocks in the way.
+    fn recover_function(&mut self) {
+        let mut brace_counter = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Fn | Token::DocComment(_) if brace_counter == 0 => return,
+                Token::LBrace => brace_counter += 1,
+                Token::RBrace if brace_counter > 0 => brace_counter -= 1,
+                _ => {}
             }
-            Token::RBrace => {
-                brace_counter -= 1;
-                token_buf.push(token);
-                if brace_counter == 0 {
-                    ast.functions.push(parse_function(token_buf.drain(..))?);
+            self.bump();
+        }
+    }
+}
+
+fn unexpected_token(token: &Tokens, expected: &str) -> Diagnostic {
+    Diagnostic::error(
+        format!("expected {expected}, found {}", token.token),
+        token.span,
+    )
+}
+
+fn unattached_doc_comment(doc: &Tokens) -> Diagnostic {
+    Diagnostic::warning("doc comment is not attached to a function", doc.span)
+        .with_help("use `//` for a comment that documents nothing")
+}
+
+/// Parses every function in `tokens`. Errors do not stop the parse, the
+/// parser skips to the next `;` or `}` and keeps going, so the returned
+/// `Ast` holds everything that parsed and the diagnostics hold the rest.
+pub fn parse_toplevel(tokens: impl IntoIterator<Item = Tokens>) -> (Ast, Vec<Diagnostic>) {
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse_program();
+
+    let mut diagnostics = parser.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    (ast, diagnostics)
+}
+
+impl Parser {
+    /// `function-def*`
+    fn parse_program(&mut self) -> Ast {
+        let mut ast = Ast::empty();
+
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Fn | Token::DocComment(_) => {
+                    if let Some(function) = self.parse_function() {
+                        ast.functions.push(function);
+                    }
+                }
+                Token::RBrace => {
+                    let token = self.bump().expect("peeked above");
+                    self.diagnostics.push(Diagnostic::error(
+                        "unexpected closing delimiter `}`",
+                        token.span,
+                    ));
+                }
+                _ => {
+                    let diagnostic = self.expected("`fn`");
+                    self.diagnostics.push(diagnostic);
+                    self.recover_function();
                 }
             }
-            _ => token_buf.push(token),
         }
-    }
 
-    Ok(ast)
+        ast
+    }
 }
 
 #[derive(Debug)]
 pub struct Scope {
     pub stmts: Vec<Statement>,
     pub ret: Option<ReturnStmts>,
+    /// trailing expression without a `;`, the value of the scope
+    pub value: Option<Box<Expression>>,
+    pub span: Span,
 }
 
-fn parse_scope(tokens: impl IntoIterator<Item = Tokens>) -> Result<Scope, ParseError> {
-    let mut token_iter = tokens.into_iter().peekable();
-    let mut brace_counter = 0;
-    let mut buf = Vec::<Tokens>::new();
-    let mut stmts = Vec::<Statement>::new();
+impl Parser {
+    /// Parses `{ ... }` into a scope. A statement that fails to parse is
+    /// reported and dropped and parsing resumes after it, only a scope
+    /// missing one of its braces is an error.
+    fn parse_block(&mut self) -> Result<Scope, Diagnostic> {
+        let open_token = self.expect(Token::LBrace)?;
+        let mut stmts = Vec::<Statement>::new();
+        let mut value = None;
+
+        let close_token = loop {
+            let stmt = match self.peek() {
+                None => return Err(Diagnostic::error("unclosed delimiter `{`", open_token.span)),
+                Some(Token::RBrace) => break self.bump().expect("peeked above"),
+                Some(Token::Semi) => {
+                    self.bump();
+                    continue;
+                }
+                Some(Token::Fn) => {
+                    let fn_span = self.tokens.peek().expect("peeked above").span;
+                    self.diagnostics.push(Diagnostic::error(
+                        "functions can only be defined at the top level",
+                        fn_span,
+                    ));
+                    self.parse_function();
+                    continue;
+                }
+                Some(token) if starts_statement(token) => self.parse_statement(),
+                Some(Token::Mult) => self
+                    .parse_place()
+                    .and_then(|place| self.parse_assignment_statement(place)),
+                // an expression right before the `}` is the value of the
+                // scope, anywhere else it starts a statement
+                Some(_) => match self.parse_expression() {
+                    Ok(expr) if matches!(self.peek(), None | Some(Token::RBrace)) => {
+                        value = Some(Box::new(expr));
+                        continue;
+                    }
+                    Ok(expr) => self.parse_expression_statement(expr),
+                    Err(diagnostic) => Err(diagnostic),
+                },
+            };
+
+            match stmt {
+                Ok(stmt) => stmts.push(stmt),
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    self.recover_statement();
+                }
+            }
+        };
 
-    while let Some(token) = token_iter.next() {
-        if brace_counter < 0 {
-            return Err(ParseError);
-        }
+        let ret = match stmts.pop() {
+            Some(Statement::Return(ret)) if value.is_none() => Some(ret),
+            Some(other) => {
+                stmts.push(other);
+                None
+            }
+            None => None,
+        };
 
-        match token.token {
-            Token::LBrace => {
-                brace_counter += 1;
-                if brace_counter > 1 {
-                    buf.push(token);
-                }
+        // a trailing `if` whose branches all end in a value, a trailing `loop`
+        // or a trailing block with a value is the value of the scope, as in
+        // `{ if a > b { a } else { b } }`
+        let value = match stmts.pop() {
+            Some(Statement::If(conditionals))
+                if value.is_none() && ret.is_none() && has_value(&conditionals) =>
+            {
+                Some(Box::new(Expression::If(Box::new(conditionals))))
             }
-            Token::RBrace => {
-                brace_counter -= 1;
-                if brace_counter == 0 {
-                    break;
-                }
-                buf.push(token);
+            Some(Statement::Loop(loops))
+                if value.is_none()
+                    && ret.is_none()
+                    && matches!(loops.kind, LoopKinds::Infinite) =>
+            {
+                Some(Box::new(Expression::Loop(Box::new(loops))))
             }
-            Token::Semi => {
-                if !buf.is_empty() {
-                    stmts.push(parse_statement(buf.drain(..))?);
-                }
+            Some(Statement::Block(block))
+                if value.is_none() && ret.is_none() && block.value.is_some() =>
+            {
+                Some(Box::new(Expression::Block(Box::new(block))))
             }
-            _ => {
-                buf.push(token);
+            Some(other) => {
+                stmts.push(other);
+                value
             }
+            None => value,
         };
-    }
 
-    if token_iter.peek().is_some() {
-        return Err(ParseError);
+        Ok(Scope {
+            stmts,
+            ret,
+            value,
+            span: open_token.span.to(close_token.span),
+        })
     }
+}
 
-    let last_stmt = stmts.pop();
-    let ret = match last_stmt.ok_or(ParseError)? {
-        Statement::Return(ret) => Some(ret),
-        other => {
-            stmts.push(other);
-            None
+/// True when every branch of `conditionals` exists and ends in a value.
+fn has_value(conditionals: &Conditionals) -> bool {
+    conditionals.then_scope.value.is_some()
+        && match &conditionals.else_branch {
+            Some(ElseBranch::ElseIf(else_if)) => has_value(else_if),
+            Some(ElseBranch::Else(else_scope)) => else_scope.value.is_some(),
+            None => false,
         }
-    };
+}
 
-    return Ok(Scope { stmts, ret });
+/// Tokens that start a statement rather than an expression.
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Return
+            | Token::Mut
+            | Token::Const
+            | Token::Break
+            | Token::Continue
+            | Token::If
+            | Token::While
+            | Token::For
+            | Token::Loop
+            | Token::Label(_)
+            | Token::LBrace
+    )
 }
 
 #[derive(Debug)]
-pub struct Functions {
-    pub fn_signature: FnSignature,
-    pub scope: Scope,
+pub struct Conditionals {
+    pub cond: Expression,
+    pub then_scope: Scope,
+    pub else_branch: Option<ElseBranch>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ElseBranch {
+    ElseIf(Box<Conditionals>),
+    Else(Scope),
 }
 
-fn parse_function(tokens: impl IntoIterator<Item = Tokens>) -> Result<Functions, ParseError> {
-    let mut buf = Vec::<Tokens>::new();
-    let mut token_iter = tokens.into_iter().peekable();
+impl Parser {
+    /// `if expr scope`, followed by any `else if` and `else` branches.
+    fn parse_if(&mut self) -> Result<Conditionals, Diagnostic> {
+        let if_token = self.expect(Token::If)?;
+        let cond = self.parse_expression()?;
+        let then_scope = self.parse_block()?;
+        let mut span = if_token.span.to(then_scope.span);
+
+        let else_branch = match self.eat(&Token::Else) {
+            Some(_) if self.at(&Token::If) => {
+                let else_if = self.parse_if()?;
+                span = span.to(else_if.span);
+                Some(ElseBranch::ElseIf(Box::new(else_if)))
+            }
+            Some(_) => {
+                let else_scope = self.parse_block()?;
+                span = span.to(else_scope.span);
+                Some(ElseBranch::Else(else_scope))
+            }
+            None => None,
+        };
 
-    while let Some(token) = token_iter.next_if(|t| t.token != Token::LBrace) {
-        buf.push(token);
+        Ok(Conditionals {
+            cond,
+            then_scope,
+            else_branch,
+            span,
+        })
     }
+}
+
+#[derive(Debug)]
+pub struct Labels {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Loops {
+    pub label: Option<Labels>,
+    pub kind: LoopKinds,
+    pub scope: Scope,
+    pub span: Span,
+    /// type the loop evaluates to, `None` while no `break` leaves it and
+    /// `()` for a `break` without a value. Filled in by semantic analysis.
+    pub value_type: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum LoopKinds {
+    /// `loop { }`, the only loop a `break` can give a value
+    Infinite,
+    While(Expression),
+    For(ForRanges),
+}
 
-    let fn_signature = parse_fn_signature(buf.drain(..))?;
+/// `for var in start..end`, `end` is exclusive.
+#[derive(Debug)]
+pub struct ForRanges {
+    pub var: String,
+    pub var_span: Span,
+    pub start: Expression,
+    pub end: Expression,
+    /// integer type of the loop variable, filled in by semantic analysis
+    pub var_type: Option<String>,
+}
+
+impl Parser {
+    /// A loop and its optional label.
+    fn parse_loop(&mut self) -> Result<Loops, Diagnostic> {
+        let label = match self.parse_label() {
+            Some(label) => {
+                self.expect(Token::Colon)?;
+                Some(label)
+            }
+            None => None,
+        };
 
-    let scope = parse_scope(token_iter)?;
+        let keyword = match self.bump_if(|t| matches!(t, Token::Loop | Token::While | Token::For)) {
+            Some(keyword) => keyword,
+            None => return Err(self.expected("`loop`, `while` or `for`")),
+        };
 
-    if !buf.is_empty() {
-        dbg!(&buf);
-        return Err(ParseError);
+        let kind = match keyword.token {
+            Token::While => LoopKinds::While(self.parse_expression()?),
+            Token::For => {
+                let (var, var_span) = self.expect_ident("loop variable")?;
+                self.expect(Token::In)?;
+                let start = self.parse_expression()?;
+                self.expect(Token::DotDot)?;
+                let end = self.parse_expression()?;
+                LoopKinds::For(ForRanges {
+                    var,
+                    var_span,
+                    start,
+                    end,
+                    var_type: None,
+                })
+            }
+            _ => LoopKinds::Infinite,
+        };
+
+        let scope = self.parse_block()?;
+        let start = label.as_ref().map_or(keyword.span, |label| label.span);
+
+        Ok(Loops {
+            label,
+            kind,
+            span: start.to(scope.span),
+            scope,
+            value_type: None,
+        })
     }
 
-    Ok(Functions {
-        fn_signature,
-        scope,
-    })
+    /// Takes the next token if it is a label.
+    fn parse_label(&mut self) -> Option<Labels> {
+        match self.bump_if(|t| matches!(t, Token::Label(_)))? {
+            Tokens {
+                token: Token::Label(name),
+                span,
+                ..
+            } => Some(Labels { name, span }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct FnSignature {
-    pub name: String,
-    //args
-    pub return_type: String,
+pub struct BreakStmts {
+    pub label: Option<Labels>,
+    pub value: Option<Expression>,
+    pub span: Span,
+    /// span of the loop this leaves, filled in by semantic analysis
+    pub target: Option<Span>,
+}
+
+#[derive(Debug)]
+pub struct ContinueStmts {
+    pub label: Option<Labels>,
+    pub span: Span,
+    /// span of the loop this continues, filled in by semantic analysis
+    pub target: Option<Span>,
 }
-fn parse_fn_signature(tokens: impl IntoIterator<Item = Tokens>) -> Result<FnSignature, ParseError> {
-    let mut token_iter = tokens.into_iter();
 
-    let fn_token = token_iter.next().ok_or(ParseError)?;
+impl Parser {
+    /// `break label? expr?`, without the `;`.
+    fn parse_break(&mut self) -> Result<BreakStmts, Diagnostic> {
+        let break_token = self.expect(Token::Break)?;
+        let label = self.parse_label();
+        let prev = label.as_ref().map_or(break_token.span, |label| label.span);
 
-    if fn_token.token != Token::Fn {
-        return Err(ParseError);
+        let value = match self.peek() {
+            None | Some(Token::Semi | Token::RBrace) => None,
+            Some(_) => Some(self.parse_expression()?),
+        };
+
+        Ok(BreakStmts {
+            span: value
+                .as_ref()
+                .map_or(prev, |value| value.span())
+                .to(break_token.span),
+            label,
+            value,
+            target: None,
+        })
     }
 
-    let fn_name = token_iter.next().ok_or(ParseError)?;
+    /// `continue label?`, without the `;`.
+    fn parse_continue(&mut self) -> Result<ContinueStmts, Diagnostic> {
+        let continue_token = self.expect(Token::Continue)?;
+        let label = self.parse_label();
+
+        Ok(ContinueStmts {
+            span: label
+                .as_ref()
+                .map_or(continue_token.span, |label| label.span)
+                .to(continue_token.span),
+            label,
+            target: None,
+        })
+    }
+}
 
-    let fn_name = match fn_name.token {
-        Token::Ident(name) => name,
-        _ => return Err(ParseError),
-    };
+#[derive(Debug)]
+pub struct Functions {
+    /// the `///` comments right before the function, one per line
+    pub doc_comments: Vec<String>,
+    pub fn_signature: FnSignature,
+    pub scope: Scope,
+}
 
-    let open_p_token = token_iter.next().ok_or(ParseError)?;
+impl Parser {
+    /// Parses a function, its body is parsed even when the signature is
+    /// broken so errors in both are reported, but only a function with a
+    /// valid signature makes it into the `Ast`.
+    fn parse_function(&mut self) -> Option<Functions> {
+        let mut doc_comments = vec![];
+        while let Some(doc) = self.bump_if(|t| matches!(t, Token::DocComment(_))) {
+            if let Token::DocComment(text) = doc.token {
+                doc_comments.push(text);
+            }
+        }
 
-    if open_p_token.token != Token::LParen {
-        return Err(ParseError);
-    }
+        let fn_signature = self
+            .parse_fn_signature()
+            .map_err(|diagnostic| self.diagnostics.push(diagnostic))
+            .ok();
 
-    // here read args
+        if fn_signature.is_none() {
+            // the body is still worth checking if it can be found
+            while !matches!(self.peek(), None | Some(Token::LBrace | Token::Fn)) {
+                self.bump();
+            }
+            if !self.at(&Token::LBrace) {
+                return None;
+            }
+        }
 
-    let close_p_token = token_iter.next().ok_or(ParseError)?;
+        let scope = match self.parse_block() {
+            Ok(scope) => scope,
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                self.recover_function();
+                return None;
+            }
+        };
 
-    if close_p_token.token != Token::RParen {
-        return Err(ParseError);
+        Some(Functions {
+            doc_comments,
+            fn_signature: fn_signature?,
+            scope,
+        })
     }
+}
 
-    let arrow_token = token_iter.next().ok_or(ParseError)?;
-
-    if arrow_token.token != Token::Arrow {
-        return Err(ParseError);
+#[derive(Debug)]
+pub struct FnSignature {
+    pub name: String,
+    pub name_span: Span,
+    pub params: Vec<Declarations>,
+    pub return_type: String,
+    pub return_type_span: Span,
+}
+impl Parser {
+    /// `fn ident ( params ) -> ident`
+    fn parse_fn_signature(&mut self) -> Result<FnSignature, Diagnostic> {
+        self.expect(Token::Fn)?;
+        let (name, name_span) = self.expect_ident("function name")?;
+        self.expect(Token::LParen)?;
+        let params = self.parse_fn_params()?;
+        self.expect(Token::Arrow)?;
+        let (return_type, return_type_span) = self.expect_ident("return type")?;
+
+        Ok(FnSignature {
+            name,
+            name_span,
+            params,
+            return_type,
+            return_type_span,
+        })
     }
 
-    let ret_type = token_iter.next().ok_or(ParseError)?;
+    /// The comma separated parameters of a function, the opening `(` has
+    /// already been consumed and the closing `)` is consumed here.
+    fn parse_fn_params(&mut self) -> Result<Vec<Declarations>, Diagnostic> {
+        let mut params = vec![];
+        if self.eat(&Token::RParen).is_some() {
+            return Ok(params);
+        }
 
-    let ret_type = match ret_type.token {
-        Token::Ident(ident) => ident,
-        _ => return Err(ParseError),
-    };
+        loop {
+            params.push(self.parse_fn_param()?);
+            match self.bump_if(|t| matches!(t, Token::Comma | Token::RParen)) {
+                Some(Tokens {
+                    token: Token::RParen,
+                    ..
+                }) => return Ok(params),
+                Some(_) => {}
+                None => return Err(self.expected("`,` or `)`")),
+            }
+        }
+    }
 
-    Ok(FnSignature {
-        name: fn_name,
-        return_type: ret_type,
-    })
+    /// `ident : ident`
+    fn parse_fn_param(&mut self) -> Result<Declarations, Diagnostic> {
+        let (ident, ident_span) = self.expect_ident("parameter name")?;
+        self.expect(Token::Colon)?;
+        let (data_type, data_type_span) = self.expect_ident("parameter type")?;
+
+        Ok(Declarations {
+            ident,
+            ident_span,
+            data_type: Some(data_type),
+            data_type_span,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct ReturnStmts {
     pub ret: Expression,
+    pub span: Span,
 }
 
-fn parse_return(tokens: impl IntoIterator<Item = Tokens>) -> Result<ReturnStmts, ParseError> {
-    let mut token_iter = tokens.into_iter();
+impl Parser {
+    /// `return expr`, without the `;`.
+    fn parse_return(&mut self) -> Result<ReturnStmts, Diagnostic> {
+        let return_token = self.expect(Token::Return)?;
 
-    let first_token = token_iter.next().ok_or(ParseError)?;
-
-    if first_token.token != Token::Return {
-        return Err(ParseError);
+        Ok(ReturnStmts {
+            ret: self.parse_expression()?,
+            span: return_token.span,
+        })
     }
-
-    Ok(ReturnStmts {
-        ret: parse_expression(token_iter)?,
-    })
 }
 
 #[derive(Debug)]
@@ -224,145 +694,514 @@ pub enum Statement {
     Return(ReturnStmts),
     Assignment(AssignmentStmts),
     Expression(Expression),
+    If(Conditionals),
+    Loop(Loops),
+    Block(Scope),
+    Break(BreakStmts),
+    Continue(ContinueStmts),
 }
 
-fn parse_statement(tokens: impl IntoIterator<Item = Tokens>) -> Result<Statement, ParseError> {
-    let mut token_iter = tokens.into_iter().peekable(); //used
-    let first_token = token_iter.peek().ok_or(ParseError)?.token.clone();
-    Ok(match &first_token {
-        Token::Return => Statement::Return(parse_return(token_iter)?),
-        Token::Mut => Statement::MutDeclaration(parse_mutable_declaration(token_iter)?),
-        Token::Const => Statement::MutDeclaration(parse_const_declaration(token_iter)?),
-        other => Statement::Assignment(parse_assignment(token_iter)?),
-    })
+impl Parser {
+    /// Statements that start with a keyword or a block, see
+    /// `parse_expression_statement` for the others.
+    fn parse_statement(&mut self) -> Result<Statement, Diagnostic> {
+        let stmt = match self.peek() {
+            Some(Token::Return) => Statement::Return(self.parse_return()?),
+            Some(Token::Mut) => Statement::MutDeclaration(self.parse_declaration()?),
+            Some(Token::Const) => Statement::ConstDeclaration(self.parse_declaration()?),
+            Some(Token::Break) => Statement::Break(self.parse_break()?),
+            Some(Token::Continue) => Statement::Continue(self.parse_continue()?),
+            // statements ending in a block need no `;`
+            Some(Token::If) => return Ok(Statement::If(self.parse_if()?)),
+            Some(Token::While | Token::For | Token::Loop | Token::Label(_)) => {
+                return Ok(Statement::Loop(self.parse_loop()?))
+            }
+            Some(Token::LBrace) => return Ok(Statement::Block(self.parse_block()?)),
+            _ => return Err(self.expected("a statement")),
+        };
+
+        self.expect_semi()?;
+        Ok(stmt)
+    }
+
+    /// `assignment ;` or `expr ;`, `lhs` is the expression the statement
+    /// starts with. An assignment to a variable starts like an expression,
+    /// one to a dereference starts with `*` and is parsed as a place.
+    fn parse_expression_statement(&mut self, lhs: Expression) -> Result<Statement, Diagnostic> {
+        let continues_place = matches!(self.peek(), Some(Token::Dot | Token::LBracket));
+        if !continues_place && self.peek().and_then(assignment_kind).is_none() {
+            self.expect_semi()?;
+            return Ok(Statement::Expression(lhs));
+        }
+
+        let place = match lhs {
+            Expression::Value(
+                token @ Tokens {
+                    token: Token::Ident(_),
+                    ..
+                },
+            ) => Place::Variable(token),
+            _ => {
+                return Err(
+                    Diagnostic::error("invalid left-hand side of assignment", lhs.span())
+                        .with_note("only variables and their parts can be assigned to"),
+                )
+            }
+        };
+        let place = self.parse_place_projections(place)?;
+        self.parse_assignment_statement(place)
+    }
+
+    /// `assignment ;` whose `place` has been parsed.
+    fn parse_assignment_statement(&mut self, place: Place) -> Result<Statement, Diagnostic> {
+        let stmt = Statement::Assignment(self.parse_assignment(place)?);
+        self.expect_semi()?;
+        Ok(stmt)
+    }
 }
 
 #[derive(Debug)]
 pub struct AssignmentStmts {
-    pub lhs: Tokens,
+    pub lhs: Place,
+    pub kind: AssignmentKinds,
     pub rhs: Expression,
 }
-fn parse_assignment(
-    tokens: impl IntoIterator<Item = Tokens>,
-) -> Result<AssignmentStmts, ParseError> {
-    let mut token_iter = tokens.into_iter();
 
-    let first_token = token_iter.next().ok_or(ParseError)?;
+/// `=` or a compound assignment, `x += 1` is `x = x + 1`. `x++` is `x += 1`
+/// and `x--` is `x -= 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignmentKinds {
+    Assign,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Increment,
+    Decrement,
+}
 
-    let eq_token = token_iter.next().ok_or(ParseError)?;
-    if eq_token.token != Token::Eq {
-        return Err(ParseError);
+impl AssignmentKinds {
+    /// The operator as written, which is also its C spelling.
+    pub fn operator(self) -> &'static str {
+        match self {
+            AssignmentKinds::Assign => "=",
+            AssignmentKinds::Add => "+=",
+            AssignmentKinds::Subtract => "-=",
+            AssignmentKinds::Multiply => "*=",
+            AssignmentKinds::Divide => "/=",
+            AssignmentKinds::Remainder => "%=",
+            AssignmentKinds::BitAnd => "&=",
+            AssignmentKinds::BitOr => "|=",
+            AssignmentKinds::BitXor => "^=",
+            AssignmentKinds::ShiftLeft => "<<=",
+            AssignmentKinds::ShiftRight => ">>=",
+            AssignmentKinds::Increment => "++",
+            AssignmentKinds::Decrement => "--",
+        }
     }
+}
 
-    let expr = parse_expression(token_iter)?;
-
-    Ok(AssignmentStmts {
-        lhs: first_token,
-        rhs: expr,
+fn assignment_kind(token: &Token) -> Option<AssignmentKinds> {
+    Some(match token {
+        Token::Eq => AssignmentKinds::Assign,
+        Token::PlusEq => AssignmentKinds::Add,
+        Token::MinusEq => AssignmentKinds::Subtract,
+        Token::MultEq => AssignmentKinds::Multiply,
+        Token::FSlashEq => AssignmentKinds::Divide,
+        Token::PercentEq => AssignmentKinds::Remainder,
+        Token::AndEq => AssignmentKinds::BitAnd,
+        Token::OrEq => AssignmentKinds::BitOr,
+        Token::CaretEq => AssignmentKinds::BitXor,
+        Token::ShlEq => AssignmentKinds::ShiftLeft,
+        Token::ShrEq => AssignmentKinds::ShiftRight,
+        Token::PlusPlus => AssignmentKinds::Increment,
+        Token::MinusMinus => AssignmentKinds::Decrement,
+        _ => return None,
     })
 }
+/// Something that can be assigned to, a variable or a part of one.
+#[derive(Debug)]
+pub enum Place {
+    /// the identifier of the variable
+    Variable(Tokens),
+    /// `place.field` and the span of the field name
+    Field(Box<Place>, String, Span),
+    /// `place[index]` and the span of the `]`
+    Index(Box<Place>, Box<Expression>, Span),
+    /// `*place` and the span of the `*`
+    Deref(Box<Place>, Span),
+}
+
+impl Place {
+    pub fn span(&self) -> Span {
+        match self {
+            Place::Variable(tok) => tok.span,
+            Place::Field(base, _, field_span) => base.span().to(*field_span),
+            Place::Index(base, _, close_span) => base.span().to(*close_span),
+            Place::Deref(base, star_span) => star_span.to(base.span()),
+        }
+    }
+
+    /// The variable the place is a part of.
+    pub fn root_mut(&mut self) -> &mut Tokens {
+        match self {
+            Place::Variable(tok) => tok,
+            Place::Field(base, ..) | Place::Index(base, ..) | Place::Deref(base, _) => {
+                base.root_mut()
+            }
+        }
+    }
+}
+
+impl Parser {
+    /// `place assign-op expr`, `place ++` or `place --`, without the `;`.
+    fn parse_assignment(&mut self, lhs: Place) -> Result<AssignmentStmts, Diagnostic> {
+        let Some(kind) = self.peek().and_then(assignment_kind) else {
+            return Err(self.expected("`=`"));
+        };
+        let mut op_token = self.bump().expect("peeked above");
+
+        let rhs = match kind {
+            // the `1` of `x += 1` stands where the `++` was written
+            AssignmentKinds::Increment | AssignmentKinds::Decrement => {
+                op_token.token = Token::IntLit(1, None);
+                Expression::Value(op_token)
+            }
+            _ => self.parse_expression()?,
+        };
+
+        Ok(AssignmentStmts { lhs, kind, rhs })
+    }
+
+    /// `* place`, or a variable followed by any number of `.field` and
+    /// `[index]`. As in Rust `*p.x` dereferences `p.x`.
+    fn parse_place(&mut self) -> Result<Place, Diagnostic> {
+        if let Some(star) = self.eat(&Token::Mult) {
+            return Ok(Place::Deref(Box::new(self.parse_place()?), star.span));
+        }
+
+        let place = match self.bump_if(|t| matches!(t, Token::Ident(_))) {
+            Some(token) => Place::Variable(token),
+            None => return Err(self.expected("a variable")),
+        };
+        self.parse_place_projections(place)
+    }
+
+    /// The `.field` and `[index]` after `place`.
+    fn parse_place_projections(&mut self, mut place: Place) -> Result<Place, Diagnostic> {
+        loop {
+            place = match self.peek() {
+                Some(Token::Dot) => {
+                    self.bump();
+                    let (field, field_span) = self.expect_ident("field name")?;
+                    Place::Field(Box::new(place), field, field_span)
+                }
+                Some(Token::LBracket) => {
+                    self.bump();
+                    let index = self.parse_expression()?;
+                    let close_token = self.expect(Token::RBracket)?;
+                    Place::Index(Box::new(place), Box::new(index), close_token.span)
+                }
+                _ => return Ok(place),
+            };
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct DeclarationStmts {
     pub lhs: Declarations,
     pub rhs: Expression,
 }
-fn parse_mutable_declaration(
-    tokens: impl IntoIterator<Item = Tokens>,
-) -> Result<DeclarationStmts, ParseError> {
-    let token_vec = tokens.into_iter().collect::<Vec<_>>();
-
-    let (lhs, rhs) = token_vec
-        .split_once(|tok| tok.token == Token::Eq)
-        .ok_or(ParseError)?;
+impl Parser {
+    /// `mut ident : ident = expr` or the same with `const`, without the `;`.
+    fn parse_declaration(&mut self) -> Result<DeclarationStmts, Diagnostic> {
+        let lhs = self.parse_declared_name()?;
+
+        if self.eat(&Token::Eq).is_none() {
+            return Err(self
+                .expected("`=`")
+                .with_help("every declaration needs an initial value"));
+        }
 
-    Ok(DeclarationStmts {
-        lhs: parse_declaration(lhs.to_vec())?,
-        rhs: parse_expression(rhs.to_vec())?,
-    })
-}
+        Ok(DeclarationStmts {
+            lhs,
+            rhs: self.parse_expression()?,
+        })
+    }
 
-fn parse_const_declaration(
-    tokens: impl IntoIterator<Item = Tokens>,
-) -> Result<DeclarationStmts, ParseError> {
-    let token_vec = tokens.into_iter().collect::<Vec<_>>();
+    /// The `mut name: type` or `const name: type` before the `=` of a
+    /// declaration, the `: type` can be left out.
+    fn parse_declared_name(&mut self) -> Result<Declarations, Diagnostic> {
+        if self
+            .bump_if(|t| matches!(t, Token::Mut | Token::Const))
+            .is_none()
+        {
+            return Err(self.expected("`mut` or `const`"));
+        }
 
-    let (lhs, rhs) = token_vec
-        .split_once(|tok| tok.token == Token::Eq)
-        .ok_or(ParseError)?;
+        let (ident, ident_span) = self.expect_ident("variable name")?;
+        let (data_type, data_type_span) = match self.eat(&Token::Colon) {
+            Some(_) => {
+                let (data_type, data_type_span) = self.expect_ident("type")?;
+                (Some(data_type), data_type_span)
+            }
+            None => (None, ident_span),
+        };
 
-    Ok(DeclarationStmts {
-        lhs: parse_declaration(lhs.to_vec())?,
-        rhs: parse_expression(rhs.to_vec())?,
-    })
+        Ok(Declarations {
+            ident,
+            ident_span,
+            data_type,
+            data_type_span,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub enum Expression {
     Addition(Box<Expression>, Box<Expression>),
+    Subtraction(Box<Expression>, Box<Expression>),
+    Multiplication(Box<Expression>, Box<Expression>),
+    Division(Box<Expression>, Box<Expression>),
+    Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+    Less(Box<Expression>, Box<Expression>),
+    LessEqual(Box<Expression>, Box<Expression>),
+    Greater(Box<Expression>, Box<Expression>),
+    GreaterEqual(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    /// the operand and the span of the `!`
+    Not(Box<Expression>, Span),
+    /// the operand and the span of the `-`
+    Negation(Box<Expression>, Span),
+    Cast(Box<Casts>),
+    BoolLit(bool, Span),
     Value(Tokens),
     FnCall(String, FnArgs),
+    If(Box<Conditionals>),
+    Loop(Box<Loops>),
+    Block(Box<Scope>),
+}
+
+impl Expression {
+    /// Span of the source code the expression was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Addition(l_expr, r_expr)
+            | Expression::Subtraction(l_expr, r_expr)
+            | Expression::Multiplication(l_expr, r_expr)
+            | Expression::Division(l_expr, r_expr)
+            | Expression::Equal(l_expr, r_expr)
+            | Expression::NotEqual(l_expr, r_expr)
+            | Expression::Less(l_expr, r_expr)
+            | Expression::LessEqual(l_expr, r_expr)
+            | Expression::Greater(l_expr, r_expr)
+            | Expression::GreaterEqual(l_expr, r_expr)
+            | Expression::And(l_expr, r_expr)
+            | Expression::Or(l_expr, r_expr) => l_expr.span().to(r_expr.span()),
+            Expression::Not(expr, bang_span) => bang_span.to(expr.span()),
+            Expression::Negation(expr, minus_span) => minus_span.to(expr.span()),
+            Expression::Cast(cast) => cast.expr.span().to(cast.data_type_span),
+            Expression::BoolLit(_, span) => *span,
+            Expression::Value(tok) => tok.span,
+            Expression::FnCall(_, fn_args) => fn_args.span,
+            Expression::If(conditionals) => conditionals.span,
+            Expression::Loop(loops) => loops.span,
+            Expression::Block(block) => block.span,
+        }
+    }
+}
+
+/// `expr as data_type`
+#[derive(Debug)]
+pub struct Casts {
+    pub expr: Expression,
+    pub data_type: String,
+    pub data_type_span: Span,
 }
 
 #[derive(Debug)]
 pub struct FnArgs {
-    args: Vec<Expression>,
+    pub args: Vec<Expression>,
+    /// the whole call, callee name included
+    pub span: Span,
 }
 
-fn parse_expression(tokens: impl IntoIterator<Item = Tokens>) -> Result<Expression, ParseError> {
-    let token_iter = tokens.into_iter().peekable(); //.collect::<Vec<Tokens>>();
+/// Binding power of a binary operator, higher binds tighter.
+/// Every binary operator is left associative.
+fn binary_precedence(token: &Token) -> Option<u8> {
+    Some(match token {
+        Token::OrOr => 1,
+        Token::AndAnd => 2,
+        Token::EqEq | Token::NotEq => 3,
+        Token::Lt | Token::LtEq | Token::Gt | Token::GtEq => 4,
+        Token::Plus | Token::Minus => 5,
+        Token::Mult | Token::FSlash => 6,
+        _ => return None,
+    })
+}
 
-    let first_tok = token_iter.next().ok_or(ParseError)?;
+impl Parser {
+    fn parse_expression(&mut self) -> Result<Expression, Diagnostic> {
+        self.parse_binary_expression(0)
+    }
 
-    let second_tok = match token_iter.next() {
-        Some(tok) => tok,
-        None => return Ok(Expression::Value(first_tok)),
-    };
+    /// Precedence climbing: parses operands and every operator binding at
+    /// least as tight as `min_precedence`, leaving looser operators to the
+    /// caller.
+    fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expression, Diagnostic> {
+        let mut lhs = self.parse_cast_expression()?;
 
-    /*if token_vec.len() == 1 {
-        return Ok(Expression::Value(token_vec[0].clone()));
-    } else {
-        let (left, right) = token_vec
-            .split_once(|t| t.token == Token::Plus)
-            .ok_or(ParseError)?;
+        while let Some(precedence) = self.peek().and_then(binary_precedence) {
+            if precedence < min_precedence {
+                break;
+            }
 
-        return Ok(Expression::Addition(
-            Box::new(parse_expression(left.to_vec())?),
-            Box::new(parse_expression(right.to_vec())?),
-        ));
-    }*/
-}
+            let op = self.bump().expect("peeked above");
+            let rhs = self.parse_binary_expression(precedence + 1)?;
+
+            let (lhs_box, rhs_box) = (Box::new(lhs), Box::new(rhs));
+            lhs = match op.token {
+                Token::Plus => Expression::Addition(lhs_box, rhs_box),
+                Token::Minus => Expression::Subtraction(lhs_box, rhs_box),
+                Token::Mult => Expression::Multiplication(lhs_box, rhs_box),
+                Token::FSlash => Expression::Division(lhs_box, rhs_box),
+                Token::EqEq => Expression::Equal(lhs_box, rhs_box),
+                Token::NotEq => Expression::NotEqual(lhs_box, rhs_box),
+                Token::Lt => Expression::Less(lhs_box, rhs_box),
+                Token::LtEq => Expression::LessEqual(lhs_box, rhs_box),
+                Token::Gt => Expression::Greater(lhs_box, rhs_box),
+                Token::GtEq => Expression::GreaterEqual(lhs_box, rhs_box),
+                Token::AndAnd => Expression::And(lhs_box, rhs_box),
+                Token::OrOr => Expression::Or(lhs_box, rhs_box),
+                _ => return Err(unexpected_token(&op, "an operator")),
+            };
+        }
 
-#[derive(Debug)]
-pub struct Declarations {
-    pub ident: String,
-    pub data_type: String,
-}
-fn parse_declaration(tokens: impl IntoIterator<Item = Tokens>) -> Result<Declarations, ParseError> {
-    let mut token_iter = tokens.into_iter();
+        Ok(lhs)
+    }
 
-    let mutability = token_iter.next().ok_or(ParseError)?;
+    /// A primary expression followed by any number of `as type` conversions,
+    /// which bind tighter than every binary operator.
+    fn parse_cast_expression(&mut self) -> Result<Expression, Diagnostic> {
+        let mut expr = self.parse_primary_expression()?;
+
+        while self.eat(&Token::As).is_some() {
+            let (data_type, data_type_span) = self.expect_ident("type")?;
+            expr = Expression::Cast(Box::new(Casts {
+                expr,
+                data_type,
+                data_type_span,
+            }));
+        }
 
-    if mutability.token != Token::Const && mutability.token != Token::Mut {
-        return Err(ParseError);
+        Ok(expr)
     }
 
-    let ident = token_iter.next().ok_or(ParseError)?;
-    let ident = match ident.token {
-        Token::Ident(ident) => ident,
-        _ => return Err(ParseError),
-    };
+    fn parse_primary_expression(&mut self) -> Result<Expression, Diagnostic> {
+        match self.peek() {
+            Some(Token::If) => return Ok(Expression::If(Box::new(self.parse_if()?))),
+            Some(Token::Loop | Token::Label(_)) => {
+                let loops = self.parse_loop()?;
+                if !matches!(loops.kind, LoopKinds::Infinite) {
+                    return Err(Diagnostic::error(
+                        "`while` and `for` loops can not be used as a value",
+                        loops.span,
+                    )
+                    .with_help("use `loop` and `break` with a value"));
+                }
+                return Ok(Expression::Loop(Box::new(loops)));
+            }
+            Some(Token::LBrace) => return Ok(Expression::Block(Box::new(self.parse_block()?))),
+            Some(
+                Token::IntLit(..)
+                | Token::FloatLit(..)
+                | Token::CharLit(_)
+                | Token::StrLit(_)
+                | Token::Ident(_)
+                | Token::BoolLit(_)
+                | Token::Bang
+                | Token::Minus
+                | Token::LParen,
+            ) => {}
+            _ => return Err(self.expected("an expression")),
+        }
 
-    let colon = token_iter.next().ok_or(ParseError)?;
-    if colon.token != Token::Colon {
-        return Err(ParseError);
+        let token = self.bump().expect("peeked above");
+        match token.token {
+            Token::Ident(name) if self.at(&Token::LParen) => {
+                self.bump();
+                let fn_args = self.parse_fn_args(token.span)?;
+                Ok(Expression::FnCall(name, fn_args))
+            }
+            Token::BoolLit(value) => Ok(Expression::BoolLit(value, token.span)),
+            Token::Bang => {
+                let operand = self.parse_primary_expression()?;
+                Ok(Expression::Not(Box::new(operand), token.span))
+            }
+            Token::Minus => {
+                let operand = self.parse_primary_expression()?;
+                Ok(Expression::Negation(Box::new(operand), token.span))
+            }
+            Token::LParen => {
+                let expr = self.parse_expression()?;
+                if self.eat(&Token::RParen).is_none() {
+                    return Err(self.expected("`)` or an operator"));
+                }
+                Ok(expr)
+            }
+            _ => Ok(Expression::Value(token)),
+        }
     }
 
-    let data_type = token_iter.next().ok_or(ParseError)?;
-    let data_type = match data_type.token {
-        Token::Ident(data_type) => data_type,
-        _ => return Err(ParseError),
-    };
+    /// Parses the comma separated arguments of a call, the opening `(` has
+    /// already been consumed and the closing `)` is consumed here.
+    fn parse_fn_args(&mut self, callee_span: Span) -> Result<FnArgs, Diagnostic> {
+        let mut args = Vec::<Expression>::new();
 
-    return Ok(Declarations { ident, data_type });
+        if let Some(close_p_token) = self.eat(&Token::RParen) {
+            return Ok(FnArgs {
+                args,
+                span: callee_span.to(close_p_token.span),
+            });
+        }
+
+        let close_p_span = loop {
+            args.push(self.parse_expression()?);
+
+            match self.bump_if(|t| matches!(t, Token::Comma | Token::RParen)) {
+                Some(Tokens {
+                    token: Token::RParen,
+                    span,
+                    ..
+                }) => break span,
+                Some(_) => {}
+                None => return Err(self.expected("`,` or `)`")),
+            }
+        };
+
+        Ok(FnArgs {
+            args,
+            span: callee_span.to(close_p_span),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Declarations {
+    pub ident: String,
+    pub ident_span: Span,
+    /// `None` for a local declared without a type, semantic analysis fills
+    /// in the type it infers
+    pub data_type: Option<String>,
+    /// the name when the type was left out
+    pub data_type_span: Span,
 }
@@ -0,0 +1,390 @@
+//! Local type inference. A `mut` or `const` declared without a type gets the
+//! type its initialiser and its later uses agree on, `mut x = 1; f(x);`
+//! declares `x` with the type of the parameter of `f`. Every expression gets
+//! a type variable, the walk unifies the variables that must be the same
+//! type, and a variable that only ever met unsuffixed literals takes the
+//! default type of the literal.
+
+use super::{FnType, Type};
+use crate::{
+    diagnostic::Span,
+    lexer::{Token, Tokens},
+    parser::{
+        AssignmentKinds, AssignmentStmts, Conditionals, DeclarationStmts, ElseBranch, Expression,
+        Functions, LoopKinds, Loops, Place, ReturnStmts, Scope, Statement,
+    },
+};
+use std::collections::HashMap;
+
+/// What is known about a type variable.
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    /// nothing yet
+    Any,
+    /// an unsuffixed integer literal, `i32` unless something says otherwise
+    Integer,
+    /// an unsuffixed float literal, `f64` unless something says otherwise
+    Float,
+    Known(Type),
+}
+
+impl Bound {
+    /// What two variables found to be the same type know together. Bounds
+    /// that disagree keep `self`, the type checker reports the mismatch once
+    /// the types are fixed.
+    fn merge(self, other: Bound) -> Bound {
+        match (self, other) {
+            (Bound::Any, other) => other,
+            (Bound::Integer, Bound::Known(ty)) if ty.is_integer() => other,
+            (Bound::Float, Bound::Known(ty)) if ty.is_float() => other,
+            (bound, _) => bound,
+        }
+    }
+
+    fn resolve(self) -> Option<Type> {
+        match self {
+            Bound::Any => None,
+            Bound::Integer => Some(Type::I32),
+            Bound::Float => Some(Type::F64),
+            Bound::Known(ty) => Some(ty),
+        }
+    }
+}
+
+struct Inference<'a> {
+    functions: &'a HashMap<String, FnType>,
+    return_type: Option<Type>,
+    /// union-find forest of the type variables
+    parents: Vec<usize>,
+    bounds: Vec<Bound>,
+    /// type variable of every name in scope, the innermost scope is last
+    scopes: Vec<HashMap<String, usize>>,
+    /// declarations without a type and the variable of their type
+    untyped: Vec<(Span, usize)>,
+}
+
+/// Infers the type of every declaration in `func` written without one,
+/// keyed by the span of the declared name. `params` are the types of the
+/// parameters of `func`. A declaration the walk can not type, like one
+/// holding the value of a `loop`, is left out and takes the type of its
+/// initialiser.
+pub(super) fn infer_function(
+    func: &Functions,
+    params: &[Option<Type>],
+    return_type: Option<Type>,
+    functions: &HashMap<String, FnType>,
+) -> HashMap<Span, Type> {
+    let mut inference = Inference {
+        functions,
+        return_type,
+        parents: vec![],
+        bounds: vec![],
+        scopes: vec![HashMap::new()],
+        untyped: vec![],
+    };
+
+    for (param, ty) in func.fn_signature.params.iter().zip(params) {
+        let var = inference.known(*ty);
+        inference.scopes[0].insert(param.ident.clone(), var);
+    }
+
+    // the value a function body ends in is what it returns
+    let ret = inference.known(return_type);
+    if let Some(value) = inference.infer_scope(&func.scope) {
+        inference.unify(ret, value);
+    }
+
+    std::mem::take(&mut inference.untyped)
+        .into_iter()
+        .filter_map(|(span, var)| {
+            let root = inference.find(var);
+            Some((span, inference.bounds[root].resolve()?))
+        })
+        .collect()
+}
+
+impl Inference<'_> {
+    fn fresh(&mut self, bound: Bound) -> usize {
+        self.parents.push(self.parents.len());
+        self.bounds.push(bound);
+        self.parents.len() - 1
+    }
+
+    /// A variable of type `ty`, or of any type when it is not known.
+    fn known(&mut self, ty: Option<Type>) -> usize {
+        self.fresh(ty.map_or(Bound::Any, Bound::Known))
+    }
+
+    fn find(&mut self, mut var: usize) -> usize {
+        while self.parents[var] != var {
+            self.parents[var] = self.parents[self.parents[var]];
+            var = self.parents[var];
+        }
+        var
+    }
+
+    /// Records that `a` and `b` are the same type. The older variable wins
+    /// a disagreement, so the first thing the code says about a type sticks
+    /// and the mismatch is reported where it is contradicted. Annotations
+    /// get their variable before the expression they constrain is walked.
+    fn unify(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        let (older, newer) = (a.min(b), a.max(b));
+        if older != newer {
+            self.parents[newer] = older;
+            self.bounds[older] = self.bounds[older].merge(self.bounds[newer]);
+        }
+    }
+
+    /// Variable of `name`, a name that does not exist gets a fresh one and
+    /// is reported by the type checker.
+    fn lookup(&mut self, name: &str) -> usize {
+        let var = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied();
+        var.unwrap_or_else(|| self.fresh(Bound::Any))
+    }
+
+    fn declare(&mut self, name: &str, var: usize) {
+        self.scopes
+            .last_mut()
+            .expect("declarations are always in a scope")
+            .insert(name.to_string(), var);
+    }
+
+    /// Walks `scope` and returns the variable of its value, if it has one.
+    fn infer_scope(&mut self, scope: &Scope) -> Option<usize> {
+        self.scopes.push(HashMap::new());
+
+        for stmt in &scope.stmts {
+            self.infer_statement(stmt);
+        }
+        if let Some(return_stmt) = &scope.ret {
+            self.infer_return(return_stmt);
+        }
+        let value = scope
+            .value
+            .as_ref()
+            .map(|value| self.infer_expression(value));
+
+        self.scopes.pop();
+        value
+    }
+
+    fn infer_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::MutDeclaration(decl_stmt) | Statement::ConstDeclaration(decl_stmt) => {
+                self.infer_declaration(decl_stmt)
+            }
+            Statement::Return(return_stmt) => self.infer_return(return_stmt),
+            Statement::Assignment(assignment_stmt) => self.infer_assignment(assignment_stmt),
+            Statement::Expression(expr) => {
+                self.infer_expression(expr);
+            }
+            Statement::If(conditionals) => {
+                self.infer_conditionals(conditionals);
+            }
+            Statement::Loop(loops) => self.infer_loop(loops),
+            Statement::Block(block) => {
+                self.infer_scope(block);
+            }
+            Statement::Break(break_stmt) => {
+                if let Some(value) = &break_stmt.value {
+                    self.infer_expression(value);
+                }
+            }
+            Statement::Continue(_) => {}
+        }
+    }
+
+    /// The initialiser is walked before the name exists, as in the type
+    /// checker.
+    fn infer_declaration(&mut self, decl_stmt: &DeclarationStmts) {
+        let decl = &decl_stmt.lhs;
+        let declared = decl
+            .data_type
+            .as_ref()
+            .map(|data_type| self.known(Type::from_name(data_type)));
+        let value = self.infer_expression(&decl_stmt.rhs);
+
+        let var = match declared {
+            Some(declared) => {
+                self.unify(declared, value);
+                declared
+            }
+            None => {
+                self.untyped.push((decl.ident_span, value));
+                value
+            }
+        };
+        self.declare(&decl.ident, var);
+    }
+
+    fn infer_return(&mut self, return_stmt: &ReturnStmts) {
+        let ret = self.known(self.return_type);
+        let value = self.infer_expression(&return_stmt.ret);
+        self.unify(ret, value);
+    }
+
+    fn infer_assignment(&mut self, assignment_stmt: &AssignmentStmts) {
+        let value = self.infer_expression(&assignment_stmt.rhs);
+        let Some(target) = self.infer_place(&assignment_stmt.lhs) else {
+            return;
+        };
+
+        // the amount of a shift can be any integer type
+        let shift = matches!(
+            assignment_stmt.kind,
+            AssignmentKinds::ShiftLeft | AssignmentKinds::ShiftRight
+        );
+        if !shift {
+            self.unify(target, value);
+        }
+    }
+
+    /// Variable of the type of `place`, `None` for a part of a variable
+    /// since no type has parts yet.
+    fn infer_place(&mut self, place: &Place) -> Option<usize> {
+        match place {
+            Place::Variable(Tokens {
+                token: Token::Ident(name),
+                ..
+            }) => Some(self.lookup(name)),
+            Place::Variable(_) => None,
+            Place::Field(base, ..) | Place::Deref(base, _) => {
+                self.infer_place(base);
+                None
+            }
+            Place::Index(base, index, _) => {
+                self.infer_place(base);
+                self.infer_expression(index);
+                None
+            }
+        }
+    }
+
+    /// Variable of the value of the `if`, every branch has the same type.
+    fn infer_conditionals(&mut self, conditionals: &Conditionals) -> usize {
+        let bool = self.known(Some(Type::Bool));
+        let cond = self.infer_expression(&conditionals.cond);
+        self.unify(bool, cond);
+
+        let then_value = self.infer_scope(&conditionals.then_scope);
+        let else_value = match &conditionals.else_branch {
+            Some(ElseBranch::ElseIf(else_if)) => Some(self.infer_conditionals(else_if)),
+            Some(ElseBranch::Else(else_scope)) => self.infer_scope(else_scope),
+            None => None,
+        };
+
+        match (then_value, else_value) {
+            (Some(then_value), Some(else_value)) => {
+                self.unify(then_value, else_value);
+                then_value
+            }
+            _ => self.fresh(Bound::Any),
+        }
+    }
+
+    fn infer_loop(&mut self, loops: &Loops) {
+        // the loop variable lives in its own scope around the body
+        self.scopes.push(HashMap::new());
+
+        match &loops.kind {
+            LoopKinds::Infinite => {}
+            LoopKinds::While(cond) => {
+                let bool = self.known(Some(Type::Bool));
+                let cond = self.infer_expression(cond);
+                self.unify(bool, cond);
+            }
+            LoopKinds::For(range) => {
+                let start = self.infer_expression(&range.start);
+                let end = self.infer_expression(&range.end);
+                self.unify(start, end);
+                self.declare(&range.var, start);
+            }
+        }
+        self.infer_scope(&loops.scope);
+
+        self.scopes.pop();
+    }
+
+    fn infer_expression(&mut self, expr: &Expression) -> usize {
+        match expr {
+            Expression::Value(tok) => match &tok.token {
+                Token::IntLit(_, None) => self.fresh(Bound::Integer),
+                Token::FloatLit(_, None) => self.fresh(Bound::Float),
+                Token::IntLit(_, Some(suffix)) | Token::FloatLit(_, Some(suffix)) => {
+                    self.known(Type::from_name(suffix))
+                }
+                Token::CharLit(_) => self.known(Some(Type::Char)),
+                Token::StrLit(_) => self.known(Some(Type::Str)),
+                Token::Ident(name) => self.lookup(name),
+                _ => self.fresh(Bound::Any),
+            },
+            Expression::Addition(l_expr, r_expr)
+            | Expression::Subtraction(l_expr, r_expr)
+            | Expression::Multiplication(l_expr, r_expr)
+            | Expression::Division(l_expr, r_expr) => {
+                let (l_var, r_var) = (self.infer_expression(l_expr), self.infer_expression(r_expr));
+                self.unify(l_var, r_var);
+                l_var
+            }
+            Expression::Equal(l_expr, r_expr)
+            | Expression::NotEqual(l_expr, r_expr)
+            | Expression::Less(l_expr, r_expr)
+            | Expression::LessEqual(l_expr, r_expr)
+            | Expression::Greater(l_expr, r_expr)
+            | Expression::GreaterEqual(l_expr, r_expr) => {
+                let (l_var, r_var) = (self.infer_expression(l_expr), self.infer_expression(r_expr));
+                self.unify(l_var, r_var);
+                self.known(Some(Type::Bool))
+            }
+            Expression::And(l_expr, r_expr) | Expression::Or(l_expr, r_expr) => {
+                let bool = self.known(Some(Type::Bool));
+                for operand in [l_expr, r_expr] {
+                    let var = self.infer_expression(operand);
+                    self.unify(bool, var);
+                }
+                bool
+            }
+            Expression::Not(operand, _) => {
+                let bool = self.known(Some(Type::Bool));
+                let var = self.infer_expression(operand);
+                self.unify(bool, var);
+                bool
+            }
+            Expression::Negation(operand, _) => self.infer_expression(operand),
+            // the operand of a conversion can be any type
+            Expression::Cast(cast) => {
+                self.infer_expression(&cast.expr);
+                self.known(Type::from_name(&cast.data_type))
+            }
+            Expression::BoolLit(..) => self.known(Some(Type::Bool)),
+            Expression::FnCall(name, fn_args) => {
+                let functions = self.functions;
+                let fn_type = functions.get(name);
+                for (i, arg) in fn_args.args.iter().enumerate() {
+                    let param = fn_type.and_then(|f| f.params.get(i).copied().flatten());
+                    let param = self.known(param);
+                    let var = self.infer_expression(arg);
+                    self.unify(param, var);
+                }
+                self.known(fn_type.and_then(|f| f.ret))
+            }
+            Expression::If(conditionals) => self.infer_conditionals(conditionals),
+            // the value of a loop is whatever its `break` gives, which is
+            // left to the type checker
+            Expression::Loop(loops) => {
+                self.infer_loop(loops);
+                self.fresh(Bound::Any)
+            }
+            Expression::Block(block) => match self.infer_scope(block) {
+                Some(value) => value,
+                None => self.fresh(Bound::Any),
+            },
+        }
+    }
+}
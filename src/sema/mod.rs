@@ -0,0 +1,1894 @@
+use crate::{
+    diagnostic::{Diagnostic, Span},
+    lexer::{Token, Tokens},
+    parser::{
+        AssignmentKinds, AssignmentStmts, Ast, BreakStmts, Casts, Conditionals, ContinueStmts,
+        DeclarationStmts, Declarations, ElseBranch, Expression, FnSignature, Functions, Labels,
+        LoopKinds, Loops, Place, ReturnStmts, Scope, Statement,
+    },
+};
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, ops::RangeInclusive};
+
+mod infer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
+    /// a unicode scalar value
+    Char,
+    Str,
+    /// type of a block that does not end in a value
+    Unit,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "i8" => Type::I8,
+            "i16" => Type::I16,
+            "i32" => Type::I32,
+            "i64" => Type::I64,
+            "u8" => Type::U8,
+            "u16" => Type::U16,
+            "u32" => Type::U32,
+            "u64" => Type::U64,
+            "f32" => Type::F32,
+            "f64" => Type::F64,
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            "str" => Type::Str,
+            _ => return None,
+        })
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.int_range().is_some()
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    pub fn is_numeric(self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Values an integer type can hold, `None` for the other types.
+    pub fn int_range(self) -> Option<RangeInclusive<i128>> {
+        Some(match self {
+            Type::I8 => i8::MIN.into()..=i8::MAX.into(),
+            Type::I16 => i16::MIN.into()..=i16::MAX.into(),
+            Type::I32 => i32::MIN.into()..=i32::MAX.into(),
+            Type::I64 => i64::MIN.into()..=i64::MAX.into(),
+            Type::U8 => 0..=u8::MAX.into(),
+            Type::U16 => 0..=u16::MAX.into(),
+            Type::U32 => 0..=u32::MAX.into(),
+            Type::U64 => 0..=u64::MAX.into(),
+            _ => return None,
+        })
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "str"),
+            Type::Unit => write!(f, "()"),
+        }
+    }
+}
+
+/// Value of an expression evaluated at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    /// wide enough for every integer type, the type is kept to check
+    /// arithmetic for overflow
+    Int(i128, Type),
+    /// `f32` values are kept rounded to `f32`
+    Float(f64, Type),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+impl ConstValue {
+    fn float(value: f64, ty: Type) -> Self {
+        match ty {
+            Type::F32 => ConstValue::Float(value as f32 as f64, ty),
+            _ => ConstValue::Float(value, ty),
+        }
+    }
+
+    /// Converts the value like the C cast `as` is lowered to, integers wrap
+    /// around. `None` when a float does not fit the integer type.
+    fn cast(self, ty: Type) -> Option<Self> {
+        if let Some(range) = ty.int_range() {
+            let value = match self {
+                ConstValue::Int(value, _) => value,
+                ConstValue::Bool(value) => value.into(),
+                ConstValue::Char(value) => u32::from(value).into(),
+                ConstValue::Str(_) => return None,
+                ConstValue::Float(value, _) => {
+                    let value = value.trunc();
+                    if !(*range.start() as f64..=*range.end() as f64).contains(&value) {
+                        return None;
+                    }
+                    value as i128
+                }
+            };
+            let size = range.end() - range.start() + 1;
+            let wrapped = (value - range.start()).rem_euclid(size) + range.start();
+            return Some(ConstValue::Int(wrapped, ty));
+        }
+
+        match (self, ty.is_float()) {
+            (ConstValue::Int(value, _), true) => Some(ConstValue::float(value as f64, ty)),
+            (ConstValue::Float(value, _), true) => Some(ConstValue::float(value, ty)),
+            (value, _) => Some(value),
+        }
+    }
+}
+
+/// A declared variable. `ty` is `None` when the declaration named an
+/// unknown type, which has already been reported. `value` is only known for
+/// `const` bindings.
+#[derive(Debug, Clone)]
+struct Symbol {
+    ty: Option<Type>,
+    mutable: bool,
+    value: Option<ConstValue>,
+    /// the variable of a `for` loop, immutable without being `const`
+    loop_variable: bool,
+    /// declared without a type, `ty` was inferred
+    inferred: bool,
+    /// name of the variable in the generated C, see `c_name`
+    c_name: String,
+}
+
+#[derive(Debug, Clone)]
+struct FnType {
+    params: Vec<Option<Type>>,
+    ret: Option<Type>,
+    span: Span,
+}
+
+/// A loop being checked, the innermost loop is last.
+#[derive(Debug)]
+struct LoopFrame {
+    label: Option<String>,
+    span: Span,
+    infinite: bool,
+    /// type of the first `break` out of the loop, `None` until there is one
+    break_type: Option<Option<Type>>,
+    /// type the context of the loop wants, see `analyze_expression`
+    expected: Option<Type>,
+}
+
+/// Stack of scopes mapping names to their declarations, the innermost scope
+/// is last.
+#[derive(Debug, Default)]
+struct SymbolTable {
+    scopes: Vec<HashMap<String, Symbol>>,
+}
+
+impl SymbolTable {
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declares `name` in the innermost scope, false when the scope already
+    /// declares it.
+    fn declare(&mut self, name: &str, symbol: Symbol) -> bool {
+        match self.scopes.last_mut() {
+            Some(scope) if !scope.contains_key(name) => {
+                scope.insert(name.to_string(), symbol);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Looks a symbol up by its C name, which is what the names in an
+    /// expression refer to once it has been analysed.
+    fn lookup_c_name(&self, c_name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.values())
+            .find(|symbol| symbol.c_name == c_name)
+    }
+}
+
+struct Context {
+    functions: HashMap<String, FnType>,
+    symbols: SymbolTable,
+    /// every variable declared in the function being checked, used to tell
+    /// a use before the declaration from a name that does not exist at all
+    fn_declarations: HashMap<String, Span>,
+    /// type of every declaration without one in the function being checked,
+    /// see `infer::infer_function`
+    inferred: HashMap<Span, Type>,
+    return_type: Option<Type>,
+    loops: Vec<LoopFrame>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Resolves every name and checks every type in `ast`, nothing is generated
+/// when this reports an error.
+pub fn semantic_analysis(ast: &mut Ast) -> Vec<Diagnostic> {
+    let mut ctx = Context {
+        functions: HashMap::new(),
+        symbols: SymbolTable::default(),
+        fn_declarations: HashMap::new(),
+        inferred: HashMap::new(),
+        return_type: None,
+        loops: vec![],
+        diagnostics: vec![],
+    };
+
+    // collect every signature first, functions can be called before they
+    // are defined
+    for func in &ast.functions {
+        declare_function(&mut ctx, &func.fn_signature);
+    }
+
+    for func in &mut ast.functions {
+        analyze_function(&mut ctx, func);
+    }
+
+    ctx.diagnostics
+}
+
+/// Error for a type name `Type::from_name` does not know, listing the ones
+/// it does.
+pub fn unknown_type(name: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("unknown type `{name}`"), span).with_help(
+        "the supported types are `i8` to `i64`, `u8` to `u64`, `f32`, `f64`, `bool`, `char` and `str`",
+    )
+}
+
+fn resolve_type(ctx: &mut Context, name: &str, span: Span) -> Option<Type> {
+    let ty = Type::from_name(name);
+    if ty.is_none() {
+        ctx.diagnostics.push(unknown_type(name, span));
+    }
+    ty
+}
+
+fn declare_function(ctx: &mut Context, fn_signature: &FnSignature) {
+    let params = fn_signature
+        .params
+        .iter()
+        .map(|param| {
+            let data_type = param.data_type.as_deref().unwrap_or_default();
+            resolve_type(ctx, data_type, param.data_type_span)
+        })
+        .collect();
+    let ret = resolve_type(
+        ctx,
+        &fn_signature.return_type,
+        fn_signature.return_type_span,
+    );
+
+    check_name(ctx, &fn_signature.name, fn_signature.name_span);
+
+    if ctx.functions.contains_key(&fn_signature.name) {
+        ctx.diagnostics.push(
+            Diagnostic::error(
+                format!("function `{}` is defined more than once", fn_signature.name),
+                fn_signature.name_span,
+            )
+            .with_note("functions can not be overloaded"),
+        );
+        return;
+    }
+
+    ctx.functions.insert(
+        fn_signature.name.clone(),
+        FnType {
+            params,
+            ret,
+            span: fn_signature.name_span,
+        },
+    );
+}
+
+fn analyze_function(ctx: &mut Context, func: &mut Functions) {
+    let fn_type = match ctx.functions.get(&func.fn_signature.name) {
+        // a second definition of the same name was reported already
+        Some(fn_type) if fn_type.span == func.fn_signature.name_span => fn_type.clone(),
+        _ => return,
+    };
+
+    ctx.return_type = fn_type.ret;
+    ctx.inferred = infer::infer_function(func, &fn_type.params, fn_type.ret, &ctx.functions);
+    ctx.fn_declarations = HashMap::new();
+    collect_declarations(&func.scope, &mut ctx.fn_declarations);
+
+    ctx.symbols.push_scope();
+
+    for (param, ty) in func.fn_signature.params.iter_mut().zip(fn_type.params) {
+        let symbol = Symbol {
+            ty,
+            mutable: true,
+            value: None,
+            loop_variable: false,
+            inferred: false,
+            c_name: c_name(ctx, &param.ident, param.ident_span),
+        };
+        declare_variable(ctx, param, symbol);
+    }
+
+    for stmt in &mut func.scope.stmts {
+        analyze_statement(ctx, stmt);
+    }
+
+    let diverges = diverges(&func.scope);
+    match (&mut func.scope.ret, &mut func.scope.value) {
+        (Some(return_stmt), _) => analyze_return(ctx, return_stmt),
+        // the value a function body ends in is what it returns
+        (None, Some(value)) => {
+            let found = analyze_scope_value(ctx, value, fn_type.ret);
+            expect_type(ctx, fn_type.ret, found, value.span());
+        }
+        (None, None) if diverges => (),
+        (None, None) => ctx.diagnostics.push(
+            Diagnostic::error(
+                format!("function `{}` does not return", func.fn_signature.name),
+                func.scope.span,
+            )
+            .with_note(format!(
+                "the function is declared to return `{}`",
+                func.fn_signature.return_type
+            ))
+            .with_help("end the function with a `return` statement"),
+        ),
+    }
+
+    ctx.symbols.pop_scope();
+}
+
+fn collect_declarations(scope: &Scope, declarations: &mut HashMap<String, Span>) {
+    for stmt in &scope.stmts {
+        match stmt {
+            Statement::MutDeclaration(decl_stmt) | Statement::ConstDeclaration(decl_stmt) => {
+                // the first declaration decides whether a use comes before it
+                declarations
+                    .entry(decl_stmt.lhs.ident.clone())
+                    .or_insert(decl_stmt.lhs.ident_span);
+            }
+            Statement::If(conditionals) => {
+                let mut conditionals = Some(conditionals);
+                while let Some(current) = conditionals {
+                    collect_declarations(&current.then_scope, declarations);
+                    conditionals = match &current.else_branch {
+                        Some(ElseBranch::ElseIf(else_if)) => Some(else_if),
+                        Some(ElseBranch::Else(else_scope)) => {
+                            collect_declarations(else_scope, declarations);
+                            None
+                        }
+                        None => None,
+                    };
+                }
+            }
+            Statement::Loop(loops) => collect_declarations(&loops.scope, declarations),
+            Statement::Block(block) => collect_declarations(block, declarations),
+            _ => (),
+        }
+    }
+}
+
+/// True when every path through `scope` ends in a `return`, or jumps out of
+/// it with `break` or `continue`.
+fn diverges(scope: &Scope) -> bool {
+    scope.ret.is_some()
+        || scope.stmts.iter().any(|stmt| match stmt {
+            Statement::Return(_) | Statement::Break(_) | Statement::Continue(_) => true,
+            Statement::If(conditionals) => conditionals_diverge(conditionals),
+            Statement::Block(block) => diverges(block),
+            // a `loop` nothing breaks out of never finishes
+            Statement::Loop(loops) => {
+                matches!(loops.kind, LoopKinds::Infinite) && loops.value_type.is_none()
+            }
+            _ => false,
+        })
+}
+
+fn conditionals_diverge(conditionals: &Conditionals) -> bool {
+    diverges(&conditionals.then_scope)
+        && match &conditionals.else_branch {
+            Some(ElseBranch::ElseIf(else_if)) => conditionals_diverge(else_if),
+            Some(ElseBranch::Else(else_scope)) => diverges(else_scope),
+            None => false,
+        }
+}
+
+/// Reports a block used as a value that is always left before it produces
+/// one, the C it becomes would have no value to give.
+fn expect_block_value(ctx: &mut Context, scope: &Scope) {
+    let note = match scope.value.as_deref() {
+        _ if diverges(scope) => "every path through it leaves with `return`, `break` or `continue`",
+        Some(Expression::Loop(loops)) if loops.value_type.is_none() => {
+            "it ends in a `loop` that no `break` leaves"
+        }
+        _ => return,
+    };
+    ctx.diagnostics.push(
+        Diagnostic::error(
+            "this block is used as a value but never produces one",
+            scope.span,
+        )
+        .with_note(note),
+    );
+}
+
+/// Reports a `loop` used as a value that no `break` leaves.
+fn expect_loop_value(ctx: &mut Context, loops: &Loops) {
+    if loops.value_type.is_none() {
+        ctx.diagnostics.push(
+            Diagnostic::error(
+                "this `loop` is used as a value but no `break` leaves it",
+                loops.span,
+            )
+            .with_help("leave it with `break` and a value, or use it as a statement"),
+        );
+    }
+}
+
+/// Checks the value a scope ends in. A trailing `loop` is not checked for a
+/// value there, codegen emits one that is never left as a statement, and a
+/// block used as a value reports it in `expect_block_value`.
+fn analyze_scope_value(
+    ctx: &mut Context,
+    value: &mut Expression,
+    expected: Option<Type>,
+) -> Option<Type> {
+    match value {
+        Expression::Loop(loops) => analyze_loop(ctx, loops, expected),
+        value => analyze_expression(ctx, value, expected),
+    }
+}
+
+/// Checks a nested block in its own scope and returns the type of its value,
+/// `None` when the block always returns or its value has an error.
+fn analyze_block(ctx: &mut Context, scope: &mut Scope, expected: Option<Type>) -> Option<Type> {
+    ctx.symbols.push_scope();
+
+    for stmt in &mut scope.stmts {
+        analyze_statement(ctx, stmt);
+    }
+
+    let diverges = diverges(scope);
+    let ty = match (&mut scope.ret, &mut scope.value) {
+        (Some(return_stmt), _) => {
+            analyze_return(ctx, return_stmt);
+            None
+        }
+        (None, Some(value)) => analyze_scope_value(ctx, value, expected),
+        (None, None) if diverges => None,
+        (None, None) => Some(Type::Unit),
+    };
+
+    ctx.symbols.pop_scope();
+    ty
+}
+
+/// Checks every branch of an `if`. Used as a value, all branches must exist
+/// and agree on a type, which is returned. Used as a statement, the value of
+/// every branch is dropped.
+fn analyze_conditionals(
+    ctx: &mut Context,
+    conditionals: &mut Conditionals,
+    as_value: bool,
+    expected: Option<Type>,
+) -> Option<Type> {
+    let cond = analyze_expression(ctx, &mut conditionals.cond, Some(Type::Bool));
+    expect_type(ctx, Some(Type::Bool), cond, conditionals.cond.span());
+
+    let then_type = analyze_block(ctx, &mut conditionals.then_scope, expected);
+    if as_value {
+        expect_block_value(ctx, &conditionals.then_scope);
+    } else {
+        warn_unused_block_value(ctx, then_type, &conditionals.then_scope);
+    }
+    let (else_type, else_span) = match &mut conditionals.else_branch {
+        Some(ElseBranch::ElseIf(else_if)) => (
+            analyze_conditionals(ctx, else_if, as_value, expected),
+            else_if.span,
+        ),
+        Some(ElseBranch::Else(else_scope)) => {
+            let else_type = analyze_block(ctx, else_scope, expected);
+            if as_value {
+                expect_block_value(ctx, else_scope);
+            } else {
+                warn_unused_block_value(ctx, else_type, else_scope);
+            }
+            (else_type, else_scope.span)
+        }
+        None if as_value => {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    "`if` without an `else` can not be used as a value",
+                    conditionals.span,
+                )
+                .with_help("add an `else` branch"),
+            );
+            return None;
+        }
+        None => return None,
+    };
+
+    if !as_value {
+        return None;
+    }
+
+    if let (Some(then_type), Some(else_type)) = (then_type, else_type) {
+        if then_type != else_type {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    format!(
+                        "`if` and `else` have incompatible types: `{then_type}` and `{else_type}`"
+                    ),
+                    else_span,
+                )
+                .with_note("both branches of an `if` used as a value must have the same type"),
+            );
+            return None;
+        }
+    }
+
+    then_type.or(else_type)
+}
+
+/// C keywords up to C23, a variable or function named like one would not
+/// compile. The ones that are also keywords here can't be names anyway.
+const C_KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "char",
+    "const",
+    "constexpr",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "nullptr",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "struct",
+    "switch",
+    "thread_local",
+    "true",
+    "typedef",
+    "typeof",
+    "typeof_unqual",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+];
+
+/// Macros of the included headers and C library functions the C compiler
+/// treats as builtins, defining one of them breaks or changes the program.
+const C_LIBRARY_NAMES: &[&str] = &[
+    "NULL",
+    "offsetof",
+    "SIZE_MAX",
+    "PTRDIFF_MIN",
+    "PTRDIFF_MAX",
+    "WCHAR_MIN",
+    "WCHAR_MAX",
+    "WINT_MIN",
+    "WINT_MAX",
+    "SIG_ATOMIC_MIN",
+    "SIG_ATOMIC_MAX",
+    "errno",
+    "stdin",
+    "stdout",
+    "stderr",
+    "abort",
+    "abs",
+    "atexit",
+    "atof",
+    "atoi",
+    "atol",
+    "bsearch",
+    "calloc",
+    "exit",
+    "free",
+    "getenv",
+    "labs",
+    "malloc",
+    "qsort",
+    "rand",
+    "realloc",
+    "srand",
+    "system",
+    "printf",
+    "fprintf",
+    "sprintf",
+    "snprintf",
+    "scanf",
+    "puts",
+    "putchar",
+    "getchar",
+    "fopen",
+    "fclose",
+    "fputs",
+    "fgets",
+    "memchr",
+    "memcmp",
+    "memcpy",
+    "memmove",
+    "memset",
+    "strcat",
+    "strchr",
+    "strcmp",
+    "strcpy",
+    "strlen",
+    "strncat",
+    "strncmp",
+    "strncpy",
+    "strrchr",
+    "strstr",
+    "acos",
+    "asin",
+    "atan",
+    "atan2",
+    "ceil",
+    "cos",
+    "cosh",
+    "exp",
+    "fabs",
+    "floor",
+    "fmod",
+    "frexp",
+    "ldexp",
+    "log",
+    "log10",
+    "modf",
+    "pow",
+    "round",
+    "sin",
+    "sinh",
+    "sqrt",
+    "tan",
+    "tanh",
+    "trunc",
+    "isalpha",
+    "isdigit",
+    "isspace",
+    "tolower",
+    "toupper",
+];
+
+/// Why `name` can't be used for a variable or function, `None` when it can.
+/// Besides the lists above C reserves `_` followed by an uppercase letter,
+/// types ending in `_t` and the `INT`/`UINT` limit macros, and the generated
+/// C uses names with `__` for its own variables and renamed bindings.
+fn reserved_name(name: &str) -> Option<String> {
+    let int_macro = (name.starts_with("INT") || name.starts_with("UINT"))
+        && ["_MAX", "_MIN", "_C", "_WIDTH"]
+            .iter()
+            .any(|end| name.ends_with(end));
+
+    if C_KEYWORDS.contains(&name) {
+        Some(format!("`{name}` is a keyword in C"))
+    } else if C_LIBRARY_NAMES.contains(&name) || int_macro {
+        Some(format!("`{name}` is defined by the C standard library"))
+    } else if name.contains("__") {
+        Some("names containing `__` are reserved for the generated C".to_string())
+    } else if name.starts_with('_') && name[1..].starts_with(|c: char| c.is_ascii_uppercase()) {
+        Some("names starting with `_` and an uppercase letter are reserved in C".to_string())
+    } else if name.ends_with("_t") {
+        Some("names ending in `_t` are reserved for C types".to_string())
+    } else {
+        None
+    }
+}
+
+/// Reports `name` when it is reserved, see `reserved_name`.
+fn check_name(ctx: &mut Context, name: &str, span: Span) {
+    if let Some(reason) = reserved_name(name) {
+        ctx.diagnostics.push(
+            Diagnostic::error(format!("`{name}` is a reserved name"), span)
+                .with_note(reason)
+                .with_help("choose a different name"),
+        );
+    }
+}
+
+/// Name the C variable for `name` gets. A binding that shadows a visible one
+/// is renamed, C would resolve `x` in `{ mut x: i32 = x + 1; }` to the new
+/// variable. So is one named like a function, in C it would hide the
+/// function from calls in its scope. The offset of the declaration keeps the
+/// new name unique.
+fn c_name(ctx: &Context, name: &str, span: Span) -> String {
+    if ctx.symbols.lookup(name).is_some() || ctx.functions.contains_key(name) {
+        format!("{name}__{}", span.start)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Declares `decl` in the innermost scope and renames it to its C name.
+fn declare_variable(ctx: &mut Context, decl: &mut Declarations, symbol: Symbol) {
+    check_name(ctx, &decl.ident, decl.ident_span);
+    let c_name = symbol.c_name.clone();
+    if !ctx.symbols.declare(&decl.ident, symbol) {
+        ctx.diagnostics.push(
+            Diagnostic::error(
+                format!("`{}` is declared more than once", decl.ident),
+                decl.ident_span,
+            )
+            .with_help("give the second declaration a different name"),
+        );
+        return;
+    }
+    decl.ident = c_name;
+}
+
+fn analyze_statement(ctx: &mut Context, stmt: &mut Statement) {
+    match stmt {
+        Statement::MutDeclaration(decl_stmt) => analyze_declaration(ctx, decl_stmt, true),
+        Statement::ConstDeclaration(decl_stmt) => analyze_declaration(ctx, decl_stmt, false),
+        Statement::Return(return_stmt) => analyze_return(ctx, return_stmt),
+        Statement::Assignment(assignment_stmt) => analyze_assignment(ctx, assignment_stmt),
+        Statement::Expression(expr) => analyze_expression_statement(ctx, expr),
+        Statement::If(conditionals) => {
+            analyze_conditionals(ctx, conditionals, false, None);
+        }
+        Statement::Loop(loops) => {
+            let ty = analyze_loop(ctx, loops, None);
+            warn_unused_value(ctx, ty, loops.span);
+        }
+        Statement::Block(block) => {
+            let ty = analyze_block(ctx, block, None);
+            warn_unused_block_value(ctx, ty, block);
+        }
+        Statement::Break(break_stmt) => analyze_break(ctx, break_stmt),
+        Statement::Continue(continue_stmt) => analyze_continue(ctx, continue_stmt),
+    }
+}
+
+/// A statement made of an expression whose value is dropped, which is only
+/// worth writing for the side effects of a call.
+fn analyze_expression_statement(ctx: &mut Context, expr: &mut Expression) {
+    let ty = analyze_expression(ctx, expr, None);
+    if !matches!(expr, Expression::FnCall(..)) {
+        warn_unused_value(ctx, ty, expr.span());
+    }
+}
+
+/// The value a block statement, or a branch of an `if` statement, ends in is
+/// dropped like the value of an expression statement.
+fn warn_unused_block_value(ctx: &mut Context, ty: Option<Type>, scope: &Scope) {
+    match scope.value.as_deref() {
+        Some(Expression::FnCall(..)) | None => {}
+        Some(value) => warn_unused_value(ctx, ty, value.span()),
+    }
+}
+
+fn warn_unused_value(ctx: &mut Context, ty: Option<Type>, span: Span) {
+    if let Some(ty) = ty.filter(|ty| *ty != Type::Unit) {
+        ctx.diagnostics.push(
+            Diagnostic::warning(format!("unused value of type `{ty}`"), span)
+                .with_help("remove the expression, or store its value in a variable"),
+        );
+    }
+}
+
+/// Checks a loop and records its type, a `loop` has the type of the values
+/// it is left with and no type at all when it is never left.
+fn analyze_loop(ctx: &mut Context, loops: &mut Loops, expected: Option<Type>) -> Option<Type> {
+    ctx.symbols.push_scope();
+
+    match &mut loops.kind {
+        LoopKinds::Infinite => (),
+        LoopKinds::While(cond) => {
+            let found = analyze_expression(ctx, cond, Some(Type::Bool));
+            expect_type(ctx, Some(Type::Bool), found, cond.span());
+        }
+        LoopKinds::For(range) => {
+            let (start_type, end_type) =
+                analyze_operands(ctx, &mut range.start, &mut range.end, None);
+            for (ty, bound) in [(start_type, &range.start), (end_type, &range.end)] {
+                if let Some(ty) = ty.filter(|ty| !ty.is_integer()) {
+                    ctx.diagnostics.push(Diagnostic::error(
+                        format!("mismatched types: expected an integer, found `{ty}`"),
+                        bound.span(),
+                    ));
+                }
+            }
+            expect_type(ctx, start_type, end_type, range.end.span());
+
+            let var_type = start_type.or(end_type).filter(|ty| ty.is_integer());
+            range.var_type = var_type.map(|ty| ty.to_string());
+
+            // the loop variable lives in its own scope around the body
+            let symbol = Symbol {
+                ty: var_type,
+                mutable: false,
+                value: None,
+                loop_variable: true,
+                inferred: false,
+                c_name: c_name(ctx, &range.var, range.var_span),
+            };
+            check_name(ctx, &range.var, range.var_span);
+            let var = std::mem::replace(&mut range.var, symbol.c_name.clone());
+            ctx.symbols.declare(&var, symbol);
+        }
+    }
+
+    ctx.loops.push(LoopFrame {
+        label: loops.label.as_ref().map(|label| label.name.clone()),
+        span: loops.span,
+        infinite: matches!(loops.kind, LoopKinds::Infinite),
+        break_type: None,
+        expected,
+    });
+    analyze_block(ctx, &mut loops.scope, None);
+    let frame = ctx.loops.pop().expect("the frame pushed above");
+
+    ctx.symbols.pop_scope();
+
+    let break_type = frame.break_type.map(|ty| ty.unwrap_or(Type::Unit));
+    loops.value_type = break_type.map(|ty| ty.to_string());
+
+    match loops.kind {
+        LoopKinds::Infinite => break_type,
+        _ => Some(Type::Unit),
+    }
+}
+
+/// Index of the loop a `break` or `continue` jumps to, the innermost loop
+/// unless a label names another one.
+fn resolve_loop(
+    ctx: &mut Context,
+    label: Option<&Labels>,
+    keyword: &str,
+    span: Span,
+) -> Option<usize> {
+    let found = match label {
+        Some(label) => ctx
+            .loops
+            .iter()
+            .rposition(|frame| frame.label.as_ref() == Some(&label.name)),
+        None => ctx.loops.len().checked_sub(1),
+    };
+
+    if found.is_none() {
+        ctx.diagnostics.push(match label {
+            Some(label) => Diagnostic::error(
+                format!("use of undeclared label `'{}`", label.name),
+                label.span,
+            ),
+            None => Diagnostic::error(format!("`{keyword}` outside of a loop"), span),
+        });
+    }
+
+    found
+}
+
+fn analyze_break(ctx: &mut Context, break_stmt: &mut BreakStmts) {
+    let index = resolve_loop(ctx, break_stmt.label.as_ref(), "break", break_stmt.span);
+
+    // the value takes the type of the values that already left the loop
+    let expected = index.and_then(|index| {
+        let frame = &ctx.loops[index];
+        frame.break_type.flatten().or(frame.expected)
+    });
+    let found = match &mut break_stmt.value {
+        Some(value) => analyze_expression(ctx, value, expected),
+        None => Some(Type::Unit),
+    };
+    let value_span = break_stmt
+        .value
+        .as_ref()
+        .map_or(break_stmt.span, |value| value.span());
+
+    let Some(index) = index else {
+        return;
+    };
+    let frame = &mut ctx.loops[index];
+    break_stmt.target = Some(frame.span);
+
+    if break_stmt.value.is_some() && !frame.infinite {
+        ctx.diagnostics.push(
+            Diagnostic::error("`break` with a value is only allowed in `loop`", value_span)
+                .with_note("`while` and `for` loops do not have a value"),
+        );
+        return;
+    }
+
+    match frame.break_type {
+        Some(expected) => expect_type(ctx, expected, found, value_span),
+        None => frame.break_type = Some(found),
+    }
+}
+
+fn analyze_continue(ctx: &mut Context, continue_stmt: &mut ContinueStmts) {
+    let index = resolve_loop(
+        ctx,
+        continue_stmt.label.as_ref(),
+        "continue",
+        continue_stmt.span,
+    );
+    continue_stmt.target = index.map(|index| ctx.loops[index].span);
+}
+
+/// A declaration without a type takes the type inferred for it, or the
+/// type of its initialiser when inference could not tell.
+fn analyze_declaration(ctx: &mut Context, decl_stmt: &mut DeclarationStmts, mutable: bool) {
+    let decl = &decl_stmt.lhs;
+    let inferred = decl.data_type.is_none();
+    let declared = match &decl.data_type {
+        Some(data_type) => resolve_type(ctx, data_type, decl.data_type_span),
+        None => ctx.inferred.get(&decl.ident_span).copied(),
+    };
+
+    // checking a literal gives it a suffix
+    let untyped_literal = is_untyped_literal(&decl_stmt.rhs);
+
+    // the initialiser is checked before the name exists, `mut x: i32 = x;`
+    // is a use before declaration
+    let errors = ctx.diagnostics.len();
+    let found = analyze_expression(ctx, &mut decl_stmt.rhs, declared);
+    expect_type(ctx, declared, found, decl_stmt.rhs.span());
+
+    let declared = match (inferred, declared.or(found)) {
+        (false, _) => declared,
+        (true, Some(Type::Unit)) => {
+            ctx.diagnostics.push(Diagnostic::error(
+                format!("`{}` can not hold a value of type `()`", decl.ident),
+                decl_stmt.rhs.span(),
+            ));
+            None
+        }
+        (true, Some(ty)) => {
+            // a literal that does not fit the type a later use decided on
+            if let Some(ty) = declared.filter(|_| untyped_literal) {
+                for diagnostic in &mut ctx.diagnostics[errors..] {
+                    diagnostic.notes.push(inferred_note(&decl.ident, ty));
+                }
+            }
+            Some(ty)
+        }
+        // neither inference nor the initialiser could tell, and nothing
+        // explains why
+        (true, None) if ctx.diagnostics.len() == errors => {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    format!("type annotations needed for `{}`", decl.ident),
+                    decl.ident_span,
+                )
+                .with_help(format!(
+                    "give `{}` a type, as in `{}: i32`",
+                    decl.ident, decl.ident
+                )),
+            );
+            None
+        }
+        (true, None) => None,
+    };
+    if inferred {
+        decl_stmt.lhs.data_type = declared.map(|ty| ty.to_string());
+    }
+    let decl = &decl_stmt.lhs;
+
+    // a `const` is folded at compile time, only evaluate initialisers that
+    // type checked so nothing is reported twice
+    let value = if mutable || ctx.diagnostics.len() > errors {
+        None
+    } else {
+        match const_eval(ctx, &decl_stmt.rhs) {
+            Ok(value) => Some(value),
+            Err(ConstEvalError::NotConstant(diagnostic)) => {
+                ctx.diagnostics.push(diagnostic.with_note(format!(
+                    "the initialiser of `const {}` must be known at compile time",
+                    decl.ident
+                )));
+                None
+            }
+            Err(ConstEvalError::Invalid(diagnostic)) => {
+                ctx.diagnostics.push(diagnostic);
+                None
+            }
+        }
+    };
+
+    let symbol = Symbol {
+        ty: declared,
+        mutable,
+        value,
+        loop_variable: false,
+        inferred,
+        c_name: c_name(ctx, &decl.ident, decl.ident_span),
+    };
+    declare_variable(ctx, &mut decl_stmt.lhs, symbol);
+}
+
+fn inferred_note(name: &str, ty: Type) -> String {
+    format!("the type of `{name}` was inferred as `{ty}`")
+}
+
+/// Only `mut` variables can be assigned to, and so can any part of one.
+fn analyze_assignment(ctx: &mut Context, assignment_stmt: &mut AssignmentStmts) {
+    let root = assignment_stmt.lhs.root_mut();
+    let name = match &root.token {
+        Token::Ident(name) => name.clone(),
+        other => {
+            ctx.diagnostics.push(
+                Diagnostic::error(format!("cannot assign to {other}"), root.span)
+                    .with_note("only variables can be assigned to"),
+            );
+            return;
+        }
+    };
+    let root_span = root.span;
+
+    let target = resolve_symbol(ctx, &name, root_span);
+    if let Some(symbol) = &target {
+        root.token = Token::Ident(symbol.c_name.clone());
+
+        if symbol.loop_variable {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    format!("cannot assign to `{name}`, it is a loop variable"),
+                    root_span,
+                )
+                .with_help(format!(
+                    "copy `{name}` into a `mut` variable to change it in the loop"
+                )),
+            );
+        } else if !symbol.mutable {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    format!("cannot assign to `{name}`, it is `const`"),
+                    root_span,
+                )
+                .with_help(format!("declare `{name}` with `mut` to make it mutable")),
+            );
+        }
+    }
+
+    let inferred = target.as_ref().is_some_and(|symbol| symbol.inferred)
+        && matches!(assignment_stmt.lhs, Place::Variable(_));
+    let root_type = target.and_then(|symbol| symbol.ty);
+    let expected = analyze_place(ctx, &mut assignment_stmt.lhs, root_type);
+    let errors = ctx.diagnostics.len();
+    let found = analyze_expression(ctx, &mut assignment_stmt.rhs, expected);
+    let rhs_checked = ctx.diagnostics.len() == errors;
+    match (assignment_stmt.kind, expected) {
+        (AssignmentKinds::Assign, _) => {
+            let errors = ctx.diagnostics.len();
+            expect_type(ctx, expected, found, assignment_stmt.rhs.span());
+            if let Some(ty) = expected.filter(|_| inferred) {
+                for diagnostic in &mut ctx.diagnostics[errors..] {
+                    diagnostic.notes.push(inferred_note(&name, ty));
+                }
+            }
+        }
+        (_, Some(target)) => {
+            analyze_compound_assignment(ctx, assignment_stmt, target, found, rhs_checked)
+        }
+        (_, None) => {}
+    }
+}
+
+/// Type of the part of its variable `place` names, `root` is the type of
+/// the variable. No type has fields, elements or a pointee yet, so only the
+/// variable itself has a type and every projection is an error.
+fn analyze_place(ctx: &mut Context, place: &mut Place, root: Option<Type>) -> Option<Type> {
+    match place {
+        Place::Variable(_) => root,
+        Place::Field(base, field, field_span) => {
+            let ty = analyze_place(ctx, base, root)?;
+            ctx.diagnostics.push(Diagnostic::error(
+                format!("no field `{field}` on type `{ty}`"),
+                *field_span,
+            ));
+            None
+        }
+        Place::Index(base, index, _) => {
+            let ty = analyze_place(ctx, base, root);
+            analyze_expression(ctx, index, None);
+            ctx.diagnostics.push(Diagnostic::error(
+                format!("cannot index into a value of type `{}`", ty?),
+                base.span(),
+            ));
+            None
+        }
+        Place::Deref(base, _) => {
+            let ty = analyze_place(ctx, base, root)?;
+            ctx.diagnostics.push(Diagnostic::error(
+                format!("type `{ty}` cannot be dereferenced"),
+                place.span(),
+            ));
+            None
+        }
+    }
+}
+
+/// `x op= y` needs `x op y` to be defined and to have the type of `x`. A
+/// shift takes any integer type on its right, the other operators need both
+/// sides to have the same type. The right side is only evaluated for the
+/// overflow and division checks when `rhs_checked`, it type checked.
+fn analyze_compound_assignment(
+    ctx: &mut Context,
+    assignment_stmt: &AssignmentStmts,
+    target: Type,
+    found: Option<Type>,
+    rhs_checked: bool,
+) {
+    let op = assignment_stmt.kind.operator();
+    let rhs_span = assignment_stmt.rhs.span();
+
+    let (defined, note) = match assignment_stmt.kind {
+        AssignmentKinds::Remainder => (target.is_integer(), "`%=` is only defined on integers"),
+        AssignmentKinds::BitAnd | AssignmentKinds::BitOr | AssignmentKinds::BitXor => (
+            target.is_integer() || target == Type::Bool,
+            "bitwise operators are only defined on integers and `bool`",
+        ),
+        AssignmentKinds::ShiftLeft | AssignmentKinds::ShiftRight => {
+            (target.is_integer(), "shifts are only defined on integers")
+        }
+        AssignmentKinds::Increment | AssignmentKinds::Decrement => (
+            target.is_integer(),
+            "`++` and `--` are only defined on integers",
+        ),
+        _ => (target.is_numeric(), "arithmetic is only defined on numbers"),
+    };
+    if !defined {
+        ctx.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot apply `{op}` to a `{target}`"),
+                assignment_stmt.lhs.span(),
+            )
+            .with_note(note),
+        );
+        return;
+    }
+
+    let Some(range) = target.int_range() else {
+        expect_type(ctx, Some(target), found, rhs_span);
+        return;
+    };
+
+    let amount = match rhs_checked.then(|| const_eval(ctx, &assignment_stmt.rhs)) {
+        Some(Ok(ConstValue::Int(amount, _))) => Some(amount),
+        _ => None,
+    };
+    match assignment_stmt.kind {
+        AssignmentKinds::ShiftLeft | AssignmentKinds::ShiftRight => {
+            if let Some(found) = found.filter(|ty| !ty.is_integer()) {
+                ctx.diagnostics.push(
+                    Diagnostic::error(format!("cannot shift by a `{found}`"), rhs_span)
+                        .with_note("the amount of a shift must be an integer"),
+                );
+                return;
+            }
+
+            // C leaves shifts by the width of the type or more undefined
+            let bits = (range.end() - range.start() + 1).ilog2();
+            if amount.is_some_and(|amount| !(0..i128::from(bits)).contains(&amount)) {
+                ctx.diagnostics.push(
+                    Diagnostic::error("this arithmetic operation will overflow", rhs_span)
+                        .with_note(format!(
+                            "a `{target}` can only be shifted by 0 to {} bits",
+                            bits - 1
+                        )),
+                );
+            }
+        }
+        kind => {
+            expect_type(ctx, Some(target), found, rhs_span);
+
+            let divides = matches!(kind, AssignmentKinds::Divide | AssignmentKinds::Remainder);
+            if divides && amount == Some(0) {
+                ctx.diagnostics.push(Diagnostic::error(
+                    "this operation will divide by zero",
+                    rhs_span,
+                ));
+            }
+        }
+    }
+}
+
+fn analyze_return(ctx: &mut Context, return_stmt: &mut ReturnStmts) {
+    let expected = ctx.return_type;
+    let found = analyze_expression(ctx, &mut return_stmt.ret, expected);
+    expect_type(ctx, expected, found, return_stmt.ret.span());
+}
+
+/// Reports a mismatch when both types are known and differ.
+fn expect_type(ctx: &mut Context, expected: Option<Type>, found: Option<Type>, span: Span) {
+    if let (Some(expected), Some(found)) = (expected, found) {
+        if expected != found {
+            ctx.diagnostics.push(Diagnostic::error(
+                format!("mismatched types: expected `{expected}`, found `{found}`"),
+                span,
+            ));
+        }
+    }
+}
+
+fn resolve_symbol(ctx: &mut Context, name: &str, span: Span) -> Option<Symbol> {
+    if let Some(symbol) = ctx.symbols.lookup(name) {
+        return Some(symbol.clone());
+    }
+
+    let diagnostic = match ctx.fn_declarations.get(name) {
+        Some(declared) if declared.start > span.start => {
+            Diagnostic::error(format!("`{name}` is used before its declaration"), span)
+                .with_help(format!("move the declaration of `{name}` above this use"))
+        }
+        // declared earlier, in a block that has ended since
+        Some(_) => Diagnostic::error(format!("cannot find `{name}` in this scope"), span)
+            .with_note(format!(
+                "`{name}` is only declared inside a block that ends before this use"
+            )),
+        None => Diagnostic::error(format!("cannot find `{name}` in this scope"), span),
+    };
+    ctx.diagnostics.push(diagnostic);
+
+    None
+}
+
+/// Type of `expr`, `None` when it could not be determined because of an
+/// error that has already been reported. `expected` is the type the context
+/// wants, an integer literal without a suffix takes it when it is an integer
+/// type and is `i32` otherwise. It is only a hint, callers still check the
+/// type they get back.
+fn analyze_expression(
+    ctx: &mut Context,
+    expr: &mut Expression,
+    expected: Option<Type>,
+) -> Option<Type> {
+    match expr {
+        Expression::Value(tok) => match &tok.token {
+            Token::IntLit(..) => {
+                let span = tok.span;
+                analyze_int_literal(ctx, tok, span, expected, false)
+            }
+            Token::FloatLit(number, suffix) => {
+                let ty = match suffix.as_deref().and_then(Type::from_name) {
+                    Some(ty) => ty,
+                    None => expected.filter(|ty| ty.is_float()).unwrap_or(Type::F64),
+                };
+                let number = number.clone();
+
+                let infinite = match ty {
+                    Type::F32 => number.parse::<f32>().is_ok_and(f32::is_infinite),
+                    _ => number.parse::<f64>().is_ok_and(f64::is_infinite),
+                };
+                if infinite {
+                    ctx.diagnostics.push(Diagnostic::error(
+                        format!("literal out of range for `{ty}`"),
+                        tok.span,
+                    ));
+                }
+
+                tok.token = Token::FloatLit(number, Some(ty.to_string()));
+                Some(ty)
+            }
+            Token::CharLit(_) => Some(Type::Char),
+            Token::StrLit(_) => Some(Type::Str),
+            Token::Ident(name) => {
+                // the name now refers to the C variable it resolved to
+                let symbol = resolve_symbol(ctx, name, tok.span)?;
+                tok.token = Token::Ident(symbol.c_name);
+                symbol.ty
+            }
+            other => {
+                ctx.diagnostics.push(Diagnostic::error(
+                    format!("expected a value, found {other}"),
+                    tok.span,
+                ));
+                None
+            }
+        },
+        Expression::Addition(l_expr, r_expr) => {
+            analyze_arithmetic(ctx, "+", l_expr, r_expr, expected)
+        }
+        Expression::Subtraction(l_expr, r_expr) => {
+            analyze_arithmetic(ctx, "-", l_expr, r_expr, expected)
+        }
+        Expression::Multiplication(l_expr, r_expr) => {
+            analyze_arithmetic(ctx, "*", l_expr, r_expr, expected)
+        }
+        Expression::Division(l_expr, r_expr) => {
+            analyze_arithmetic(ctx, "/", l_expr, r_expr, expected)
+        }
+        Expression::Equal(l_expr, r_expr) => analyze_comparison(ctx, "==", l_expr, r_expr, false),
+        Expression::NotEqual(l_expr, r_expr) => {
+            analyze_comparison(ctx, "!=", l_expr, r_expr, false)
+        }
+        Expression::Less(l_expr, r_expr) => analyze_comparison(ctx, "<", l_expr, r_expr, true),
+        Expression::LessEqual(l_expr, r_expr) => {
+            analyze_comparison(ctx, "<=", l_expr, r_expr, true)
+        }
+        Expression::Greater(l_expr, r_expr) => analyze_comparison(ctx, ">", l_expr, r_expr, true),
+        Expression::GreaterEqual(l_expr, r_expr) => {
+            analyze_comparison(ctx, ">=", l_expr, r_expr, true)
+        }
+        Expression::And(l_expr, r_expr) => {
+            analyze_logical(ctx, "&&", [l_expr.as_mut(), r_expr.as_mut()])
+        }
+        Expression::Or(l_expr, r_expr) => {
+            analyze_logical(ctx, "||", [l_expr.as_mut(), r_expr.as_mut()])
+        }
+        Expression::Not(expr, _) => analyze_logical(ctx, "!", [expr.as_mut()]),
+        Expression::Negation(operand, minus_span) => {
+            analyze_negation(ctx, operand, *minus_span, expected)
+        }
+        Expression::Cast(cast) => analyze_cast(ctx, cast),
+        Expression::BoolLit(..) => Some(Type::Bool),
+        Expression::If(conditionals) => analyze_conditionals(ctx, conditionals, true, expected),
+        Expression::Loop(loops) => {
+            let ty = analyze_loop(ctx, loops, expected);
+            expect_loop_value(ctx, loops);
+            ty
+        }
+        Expression::Block(block) => {
+            let ty = analyze_block(ctx, block, expected);
+            expect_block_value(ctx, block);
+            ty
+        }
+        Expression::FnCall(name, fn_args) => {
+            let fn_type = ctx.functions.get(name).cloned();
+            let arg_types = fn_args
+                .args
+                .iter_mut()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let param = fn_type.as_ref().and_then(|f| f.params.get(i).copied());
+                    analyze_expression(ctx, arg, param.flatten())
+                })
+                .collect::<Vec<_>>();
+
+            let fn_type = match fn_type {
+                Some(fn_type) => fn_type,
+                None => {
+                    ctx.diagnostics.push(Diagnostic::error(
+                        format!("cannot find function `{name}`"),
+                        fn_args.span,
+                    ));
+                    return None;
+                }
+            };
+
+            if fn_type.params.len() != arg_types.len() {
+                ctx.diagnostics.push(Diagnostic::error(
+                    format!(
+                        "function `{name}` takes {} argument(s) but {} were supplied",
+                        fn_type.params.len(),
+                        arg_types.len()
+                    ),
+                    fn_args.span,
+                ));
+                return fn_type.ret;
+            }
+
+            for ((expected, found), arg) in fn_type.params.iter().zip(arg_types).zip(&fn_args.args)
+            {
+                expect_type(ctx, *expected, found, arg.span());
+            }
+
+            fn_type.ret
+        }
+    }
+}
+
+/// Types the integer literal `tok`, `negated` when it is the operand of a
+/// unary `-` so that the range check sees its sign and `-128i8` fits.
+fn analyze_int_literal(
+    ctx: &mut Context,
+    tok: &mut Tokens,
+    span: Span,
+    expected: Option<Type>,
+    negated: bool,
+) -> Option<Type> {
+    let &Token::IntLit(num, ref suffix) = &tok.token else {
+        unreachable!("only called on integer literals")
+    };
+    let ty = match suffix.as_deref().and_then(Type::from_name) {
+        Some(ty) => ty,
+        None => expected.filter(|ty| ty.is_integer()).unwrap_or(Type::I32),
+    };
+
+    // negating an unsigned literal is reported by `analyze_negation`
+    let range = ty.int_range()?;
+    let value = if negated {
+        -i128::from(num)
+    } else {
+        num.into()
+    };
+    if !range.contains(&value) && !(negated && *range.start() == 0) {
+        ctx.diagnostics.push(
+            Diagnostic::error(format!("literal out of range for `{ty}`"), span).with_note(format!(
+                "`{ty}` holds values from {} to {}",
+                range.start(),
+                range.end()
+            )),
+        );
+    }
+
+    // the literal keeps the type it was given for codegen
+    tok.token = Token::IntLit(num, Some(ty.to_string()));
+    Some(ty)
+}
+
+/// Unary `-` is defined on signed integers and floats.
+fn analyze_negation(
+    ctx: &mut Context,
+    operand: &mut Expression,
+    minus_span: Span,
+    expected: Option<Type>,
+) -> Option<Type> {
+    if let Expression::Negation(_, inner_minus) = operand {
+        if inner_minus.start == minus_span.end {
+            ctx.diagnostics.push(
+                Diagnostic::warning("use of a double negation", minus_span.to(operand.span()))
+                    .with_note("there is no prefix `--`, this negates the value twice")
+                    .with_help("use `x--;` to decrement a variable, or write `-(-x)`"),
+            );
+        }
+    }
+
+    let ty = match &mut *operand {
+        Expression::Value(tok) if matches!(tok.token, Token::IntLit(..)) => {
+            let span = minus_span.to(tok.span);
+            analyze_int_literal(ctx, tok, span, expected, true)
+        }
+        operand => analyze_expression(ctx, operand, expected),
+    }?;
+
+    let unsigned = ty.int_range().is_some_and(|range| *range.start() == 0);
+    if ty.is_numeric() && !unsigned {
+        return Some(ty);
+    }
+
+    let diagnostic = Diagnostic::error(
+        format!("cannot apply unary `-` to a `{ty}`"),
+        minus_span.to(operand.span()),
+    );
+    ctx.diagnostics.push(match ty {
+        _ if unsigned => diagnostic.with_note("unsigned integers can not be negative"),
+        Type::Bool => diagnostic
+            .with_note("negation is only defined on signed numbers")
+            .with_help("use `!` to negate a `bool`"),
+        _ => diagnostic.with_note("negation is only defined on signed numbers"),
+    });
+    None
+}
+
+/// `as` converts between any two numeric types, and from `bool` or `char`
+/// to an integer.
+fn analyze_cast(ctx: &mut Context, cast: &mut Casts) -> Option<Type> {
+    let from = analyze_expression(ctx, &mut cast.expr, None);
+    let to = resolve_type(ctx, &cast.data_type, cast.data_type_span)?;
+
+    let from = from?;
+    let allowed = from == to
+        || (from.is_numeric() && to.is_numeric())
+        || (matches!(from, Type::Bool | Type::Char) && to.is_integer());
+    if !allowed {
+        ctx.diagnostics.push(
+            Diagnostic::error(
+                format!("cannot cast `{from}` as `{to}`"),
+                cast.expr.span().to(cast.data_type_span),
+            )
+            .with_note("`as` converts between numbers, and from `bool` or `char` to integers"),
+        );
+    }
+
+    Some(to)
+}
+
+/// True for integer literals without a suffix and arithmetic on nothing
+/// but those, they take their type from what they are combined with.
+fn is_untyped_literal(expr: &Expression) -> bool {
+    match expr {
+        Expression::Value(tok) => {
+            matches!(tok.token, Token::IntLit(_, None) | Token::FloatLit(_, None))
+        }
+        Expression::Addition(l_expr, r_expr)
+        | Expression::Subtraction(l_expr, r_expr)
+        | Expression::Multiplication(l_expr, r_expr)
+        | Expression::Division(l_expr, r_expr) => {
+            is_untyped_literal(l_expr) && is_untyped_literal(r_expr)
+        }
+        Expression::Negation(operand, _) => is_untyped_literal(operand),
+        _ => false,
+    }
+}
+
+/// Analyses both operands of a binary operator. An operand that is only
+/// untyped literals goes last so it takes the type of the other one, `1 + x`
+/// adds two `u8` when `x` is one.
+fn analyze_operands(
+    ctx: &mut Context,
+    l_expr: &mut Expression,
+    r_expr: &mut Expression,
+    expected: Option<Type>,
+) -> (Option<Type>, Option<Type>) {
+    if is_untyped_literal(l_expr) && !is_untyped_literal(r_expr) {
+        let r_type = analyze_expression(ctx, r_expr, expected);
+        let l_type = analyze_expression(ctx, l_expr, r_type.or(expected));
+        (l_type, r_type)
+    } else {
+        let l_type = analyze_expression(ctx, l_expr, expected);
+        let r_type = analyze_expression(ctx, r_expr, l_type.or(expected));
+        (l_type, r_type)
+    }
+}
+
+/// Arithmetic is defined on two numbers of the same type, mixing integers and
+/// floats, widths or signedness needs an explicit conversion with `as`.
+fn analyze_arithmetic(
+    ctx: &mut Context,
+    op: &str,
+    l_expr: &mut Expression,
+    r_expr: &mut Expression,
+    expected: Option<Type>,
+) -> Option<Type> {
+    let (l_type, r_type) = analyze_operands(ctx, l_expr, r_expr, expected);
+
+    for (ty, expr) in [(l_type, &*l_expr), (r_type, &*r_expr)] {
+        if let Some(ty) = ty.filter(|ty| !ty.is_numeric()) {
+            let mut diagnostic =
+                Diagnostic::error(format!("cannot apply `{op}` to a `{ty}`"), expr.span())
+                    .with_note("arithmetic is only defined on numbers");
+            if ty == Type::Bool {
+                diagnostic = diagnostic.with_help("use `as i32` to turn a `bool` into `0` or `1`");
+            }
+            ctx.diagnostics.push(diagnostic);
+            return None;
+        }
+    }
+
+    match (l_type, r_type) {
+        (Some(l_ty), Some(r_ty)) if l_ty != r_ty => {
+            ctx.diagnostics.push(
+                Diagnostic::error(
+                    format!("cannot apply `{op}` to `{l_ty}` and `{r_ty}`"),
+                    l_expr.span().to(r_expr.span()),
+                )
+                .with_note("numbers of different types are never converted implicitly")
+                .with_help(format!("convert one side with `as {l_ty}` or `as {r_ty}`")),
+            );
+            None
+        }
+        _ => l_type.or(r_type),
+    }
+}
+
+/// Both sides of a comparison must have the same type, ordering is only
+/// defined on numbers and `char`.
+fn analyze_comparison(
+    ctx: &mut Context,
+    op: &str,
+    l_expr: &mut Expression,
+    r_expr: &mut Expression,
+    ordered: bool,
+) -> Option<Type> {
+    let (l_type, r_type) = analyze_operands(ctx, l_expr, r_expr, None);
+
+    for (ty, expr) in [(l_type, &*l_expr), (r_type, &*r_expr)] {
+        if let Some(ty) = ty.filter(|ty| ordered && !ty.is_numeric() && *ty != Type::Char) {
+            ctx.diagnostics.push(
+                Diagnostic::error(format!("cannot apply `{op}` to a `{ty}`"), expr.span())
+                    .with_note("ordering is only defined on numbers and `char`"),
+            );
+            return Some(Type::Bool);
+        }
+        if ty == Some(Type::Str) {
+            ctx.diagnostics.push(
+                Diagnostic::error(format!("cannot apply `{op}` to a `str`"), expr.span())
+                    .with_note("strings can not be compared yet"),
+            );
+            return Some(Type::Bool);
+        }
+    }
+
+    expect_type(ctx, l_type, r_type, r_expr.span());
+    Some(Type::Bool)
+}
+
+fn analyze_logical<'a>(
+    ctx: &mut Context,
+    op: &str,
+    operands: impl IntoIterator<Item = &'a mut Expression>,
+) -> Option<Type> {
+    for expr in operands {
+        let ty = analyze_expression(ctx, expr, Some(Type::Bool));
+        if let Some(ty) = ty.filter(|ty| *ty != Type::Bool) {
+            let mut diagnostic =
+                Diagnostic::error(format!("cannot apply `{op}` to a `{ty}`"), expr.span())
+                    .with_note("logical operators are only defined on `bool`");
+            if ty.is_numeric() {
+                diagnostic = diagnostic.with_help("compare it to get a `bool`, as in `x != 0`");
+            }
+            ctx.diagnostics.push(diagnostic);
+        }
+    }
+
+    Some(Type::Bool)
+}
+
+/// Why `const_eval` could not fold an expression.
+enum ConstEvalError {
+    /// the value is only known at runtime
+    NotConstant(Diagnostic),
+    /// the value is known but invalid, it overflows or divides by zero
+    Invalid(Diagnostic),
+}
+
+impl ConstEvalError {
+    fn not_constant(message: impl ToString, span: Span) -> Self {
+        ConstEvalError::NotConstant(Diagnostic::error(message, span))
+    }
+
+    fn invalid(message: impl ToString, span: Span) -> Self {
+        ConstEvalError::Invalid(Diagnostic::error(message, span))
+    }
+}
+
+/// Folds a block, only blocks that are nothing but a value can be folded.
+fn const_eval_scope(ctx: &Context, scope: &Scope) -> Result<ConstValue, ConstEvalError> {
+    match (&scope.value, scope.stmts.is_empty() && scope.ret.is_none()) {
+        (Some(value), true) => const_eval(ctx, value),
+        _ => Err(ConstEvalError::not_constant(
+            "a block with statements can not be evaluated at compile time",
+            scope.span,
+        )),
+    }
+}
+
+/// Folds a type checked expression to its value, failing on anything that is
+/// only known at runtime and on arithmetic that would overflow or divide by
+/// zero.
+fn const_eval(ctx: &Context, expr: &Expression) -> Result<ConstValue, ConstEvalError> {
+    // integer results have to fit the type of the operands
+    let arithmetic = |l_expr: &Expression,
+                      r_expr: &Expression,
+                      int_op: fn(i128, i128) -> Option<i128>,
+                      float_op: fn(f64, f64) -> f64| {
+        match (const_eval(ctx, l_expr)?, const_eval(ctx, r_expr)?) {
+            (ConstValue::Int(l, ty), ConstValue::Int(r, _)) => match int_op(l, r) {
+                Some(value) if ty.int_range().is_some_and(|range| range.contains(&value)) => {
+                    Ok(ConstValue::Int(value, ty))
+                }
+                _ => Err(ConstEvalError::invalid(
+                    "this arithmetic operation will overflow",
+                    expr.span(),
+                )),
+            },
+            (ConstValue::Float(l, ty), ConstValue::Float(r, _)) => {
+                Ok(ConstValue::float(float_op(l, r), ty))
+            }
+            _ => Err(ConstEvalError::invalid(
+                "arithmetic on a non numeric constant",
+                expr.span(),
+            )),
+        }
+    };
+    let ordering = |l_expr: &Expression, r_expr: &Expression| match (
+        const_eval(ctx, l_expr)?,
+        const_eval(ctx, r_expr)?,
+    ) {
+        (ConstValue::Int(l, _), ConstValue::Int(r, _)) => Ok(l.partial_cmp(&r)),
+        (ConstValue::Float(l, _), ConstValue::Float(r, _)) => Ok(l.partial_cmp(&r)),
+        (ConstValue::Char(l), ConstValue::Char(r)) => Ok(l.partial_cmp(&r)),
+        _ => Err(ConstEvalError::invalid(
+            "comparison of non numeric constants",
+            expr.span(),
+        )),
+    };
+    let bool_operand = |expr: &Expression| match const_eval(ctx, expr)? {
+        ConstValue::Bool(value) => Ok(value),
+        _ => Err(ConstEvalError::invalid(
+            "logic on a non boolean constant",
+            expr.span(),
+        )),
+    };
+
+    Ok(match expr {
+        Expression::Value(tok) => match &tok.token {
+            Token::IntLit(num, suffix) => {
+                let ty = suffix.as_deref().and_then(Type::from_name);
+                ConstValue::Int((*num).into(), ty.unwrap_or(Type::I32))
+            }
+            Token::FloatLit(number, suffix) => {
+                let ty = suffix.as_deref().and_then(Type::from_name);
+                let value = number.parse().expect("the lexer only accepts valid floats");
+                ConstValue::float(value, ty.unwrap_or(Type::F64))
+            }
+            Token::CharLit(value) => ConstValue::Char(*value),
+            Token::StrLit(value) => ConstValue::Str(value.clone()),
+            Token::Ident(name) => match ctx.symbols.lookup_c_name(name) {
+                Some(Symbol {
+                    value: Some(value), ..
+                }) => value.clone(),
+                _ => {
+                    return Err(ConstEvalError::NotConstant(
+                        Diagnostic::error(format!("`{name}` is not a constant"), tok.span)
+                            .with_help(format!(
+                                "declare `{name}` with `const` to use it in a constant"
+                            )),
+                    ))
+                }
+            },
+            other => {
+                return Err(ConstEvalError::not_constant(
+                    format!("{other} is not a constant"),
+                    tok.span,
+                ))
+            }
+        },
+        Expression::Addition(l_expr, r_expr) => {
+            arithmetic(l_expr, r_expr, i128::checked_add, |l, r| l + r)?
+        }
+        Expression::Subtraction(l_expr, r_expr) => {
+            arithmetic(l_expr, r_expr, i128::checked_sub, |l, r| l - r)?
+        }
+        Expression::Multiplication(l_expr, r_expr) => {
+            arithmetic(l_expr, r_expr, i128::checked_mul, |l, r| l * r)?
+        }
+        Expression::Division(l_expr, r_expr) => {
+            // float division by zero is fine, it gives an infinity
+            if let ConstValue::Int(0, _) = const_eval(ctx, r_expr)? {
+                return Err(ConstEvalError::invalid(
+                    "this operation will divide by zero",
+                    expr.span(),
+                ));
+            }
+            arithmetic(l_expr, r_expr, i128::checked_div, |l, r| l / r)?
+        }
+        Expression::Equal(l_expr, r_expr) => {
+            ConstValue::Bool(const_eval(ctx, l_expr)? == const_eval(ctx, r_expr)?)
+        }
+        Expression::NotEqual(l_expr, r_expr) => {
+            ConstValue::Bool(const_eval(ctx, l_expr)? != const_eval(ctx, r_expr)?)
+        }
+        Expression::Less(l_expr, r_expr) => {
+            ConstValue::Bool(ordering(l_expr, r_expr)? == Some(Ordering::Less))
+        }
+        Expression::LessEqual(l_expr, r_expr) => ConstValue::Bool(matches!(
+            ordering(l_expr, r_expr)?,
+            Some(Ordering::Less | Ordering::Equal)
+        )),
+        Expression::Greater(l_expr, r_expr) => {
+            ConstValue::Bool(ordering(l_expr, r_expr)? == Some(Ordering::Greater))
+        }
+        Expression::GreaterEqual(l_expr, r_expr) => ConstValue::Bool(matches!(
+            ordering(l_expr, r_expr)?,
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        // short circuits like the generated code would
+        Expression::And(l_expr, r_expr) => {
+            ConstValue::Bool(bool_operand(l_expr)? && bool_operand(r_expr)?)
+        }
+        Expression::Or(l_expr, r_expr) => {
+            ConstValue::Bool(bool_operand(l_expr)? || bool_operand(r_expr)?)
+        }
+        Expression::Not(expr, _) => ConstValue::Bool(!bool_operand(expr)?),
+        Expression::Negation(operand, _) => match const_eval(ctx, operand)? {
+            ConstValue::Int(value, ty) if ty.int_range().is_some_and(|r| r.contains(&-value)) => {
+                ConstValue::Int(-value, ty)
+            }
+            ConstValue::Int(..) => {
+                return Err(ConstEvalError::invalid(
+                    "this arithmetic operation will overflow",
+                    expr.span(),
+                ))
+            }
+            ConstValue::Float(value, ty) => ConstValue::Float(-value, ty),
+            _ => {
+                return Err(ConstEvalError::invalid(
+                    "arithmetic on a non numeric constant",
+                    expr.span(),
+                ))
+            }
+        },
+        Expression::BoolLit(value, _) => ConstValue::Bool(*value),
+        Expression::Cast(cast) => {
+            let ty = Type::from_name(&cast.data_type).ok_or_else(|| {
+                ConstEvalError::Invalid(unknown_type(&cast.data_type, cast.data_type_span))
+            })?;
+            const_eval(ctx, &cast.expr)?.cast(ty).ok_or_else(|| {
+                ConstEvalError::invalid(format!("this value does not fit in `{ty}`"), expr.span())
+            })?
+        }
+        Expression::If(conditionals) => {
+            let mut conditionals: &Conditionals = conditionals;
+            loop {
+                let scope = if bool_operand(&conditionals.cond)? {
+                    &conditionals.then_scope
+                } else {
+                    match &conditionals.else_branch {
+                        Some(ElseBranch::ElseIf(else_if)) => {
+                            conditionals = else_if;
+                            continue;
+                        }
+                        Some(ElseBranch::Else(else_scope)) => else_scope,
+                        None => {
+                            return Err(ConstEvalError::invalid(
+                                "`if` without an `else` can not be used as a value",
+                                conditionals.span,
+                            ))
+                        }
+                    }
+                };
+
+                break const_eval_scope(ctx, scope)?;
+            }
+        }
+        Expression::Block(block) => const_eval_scope(ctx, block)?,
+        Expression::Loop(loops) => {
+            return Err(ConstEvalError::not_constant(
+                "a loop can not be evaluated at compile time",
+                loops.span,
+            ))
+        }
+        Expression::FnCall(name, fn_args) => {
+            return Err(ConstEvalError::not_constant(
+                format!("the call to `{name}` can not be evaluated at compile time"),
+                fn_args.span,
+            ))
+        }
+    })
+}
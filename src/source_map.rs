@@ -0,0 +1,104 @@
+use crate::diagnostic::Span;
+use std::path::{Path, PathBuf};
+
+/// Index of a file in a `SourceMap`, every `Span` says which file it is in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+/// Line and column of a byte offset, both counted from 0. Columns count
+/// characters rather than bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+    /// byte offset of the start of every line
+    line_starts: Vec<usize>,
+}
+
+/// The files of a compilation and their source, resolves spans back to the
+/// code they were lexed from.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Registers the file at `path` before it is read, tokens lexed from it
+    /// carry the returned id. Its source is added with `set_source`.
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> FileId {
+        self.files.push(SourceFile {
+            path: path.as_ref().to_path_buf(),
+            source: String::new(),
+            line_starts: vec![0],
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn set_source(&mut self, file_id: FileId, source: impl Into<String>) {
+        let file = &mut self.files[file_id.0];
+        file.source = source.into();
+        file.line_starts = std::iter::once(0)
+            .chain(file.source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+    }
+
+    pub fn path(&self, file_id: FileId) -> &Path {
+        &self.files[file_id.0].path
+    }
+
+    pub fn source(&self, file_id: FileId) -> &str {
+        &self.files[file_id.0].source
+    }
+
+    /// Position of the start of `span`.
+    pub fn line_column(&self, span: Span) -> LineColumn {
+        let file = &self.files[span.file_id.0];
+        let start = clamp(&file.source, span.start);
+        let line = file
+            .line_starts
+            .partition_point(|&line_start| line_start <= start)
+            - 1;
+
+        LineColumn {
+            line,
+            column: file.source[file.line_starts[line]..start].chars().count(),
+        }
+    }
+
+    /// The line `span` starts on without its line break, and the byte offset
+    /// the line starts at.
+    pub fn line(&self, span: Span) -> (usize, &str) {
+        let file = &self.files[span.file_id.0];
+        let line_start = file.line_starts[self.line_column(span).line];
+        let line_end = file.source[line_start..]
+            .find('\n')
+            .map_or(file.source.len(), |i| line_start + i);
+
+        (
+            line_start,
+            file.source[line_start..line_end].trim_end_matches('\r'),
+        )
+    }
+
+    /// The code `span` covers.
+    pub fn excerpt(&self, span: Span) -> &str {
+        let source = self.source(span.file_id);
+        let start = clamp(source, span.start);
+        &source[start..clamp(source, span.end).max(start)]
+    }
+}
+
+/// `offset` moved back into `source` and onto the start of a character.
+fn clamp(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}